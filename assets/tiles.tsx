@@ -0,0 +1,4 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.4" name="tiles" tilewidth="64" tileheight="64" tilecount="14" columns="14">
+ <image source="tiles.png" width="896" height="64"/>
+</tileset>
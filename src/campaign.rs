@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::header::LevelHeader;
+use crate::level::Level;
+use crate::player::Player;
+
+/// Ordered list of levels, with the progress of the current playthrough.
+///
+/// The manifest is a text file with one level file per line, empty lines and
+/// lines starting with `#` are ignored. The number of unlocked levels is kept
+/// in a progress file so it survives between sessions.
+#[derive(Debug)]
+pub struct Campaign {
+    levels: Vec<String>,
+    names: Vec<String>,
+    current: usize,
+    unlocked: usize,
+    // Score and time carried from the levels already completed
+    pub score: i32,
+    pub time: Duration,
+    /// Lives left, carried across restarts and levels until they run out
+    pub lives: u32,
+}
+
+impl Campaign {
+    const PROGRESS_FILE: &'static str = "progress.txt";
+
+    pub fn from_file(filename: &str) -> Result<Campaign, String> {
+        let manifest = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        let levels: Vec<String> = manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        if levels.is_empty() {
+            return Err(format!("{}: no levels in campaign", filename));
+        }
+
+        // Levels without a name in their header are named after the file
+        let names = levels
+            .iter()
+            .map(|file| {
+                let header =
+                    LevelHeader::from_file(file).map_err(|e| format!("{}: {}", file, e))?;
+                let stem = Path::new(file).file_stem().and_then(|s| s.to_str()).unwrap_or(file);
+                Ok(header.name.unwrap_or_else(|| stem.to_string()))
+            })
+            .collect::<Result<_, String>>()?;
+
+        let unlocked = fs::read_to_string(Campaign::PROGRESS_FILE)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(1)
+            .clamp(1, levels.len());
+
+        Ok(Campaign {
+            levels,
+            names,
+            current: 0,
+            unlocked,
+            score: 0,
+            time: Duration::ZERO,
+            lives: Player::LIVES,
+        })
+    }
+
+    pub fn level_file(&self) -> &str {
+        &self.levels[self.current]
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn unlocked(&self) -> usize {
+        self.unlocked
+    }
+
+    pub fn last(&self) -> bool {
+        self.current + 1 == self.levels.len()
+    }
+
+    /// Display names of the unlocked levels
+    pub fn level_names(&self) -> Vec<String> {
+        self.names[..self.unlocked]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("{}. {}", i + 1, name))
+            .collect()
+    }
+
+    /// Loads the current level, starting with the score and lives carried from the
+    /// previous ones
+    pub fn load_level(&self) -> Result<Level, String> {
+        let mut level = Level::from_file(self.level_file())
+            .map_err(|e| format!("{}: {}", self.level_file(), e))?;
+        level.score = self.score;
+        level.player.lives = self.lives;
+        Ok(level)
+    }
+
+    /// Starts a new playthrough from one of the unlocked levels
+    pub fn select(&mut self, index: usize) {
+        self.current = index.min(self.unlocked - 1);
+        self.score = 0;
+        self.time = Duration::ZERO;
+        self.lives = Player::LIVES;
+    }
+
+    /// Restarts the current level with the lives left, or as a new playthrough once the
+    /// last one is lost
+    pub fn restart(&mut self, level: &Level) {
+        if level.player.lives == 0 {
+            self.select(self.current);
+        } else {
+            self.lives = level.player.lives;
+        }
+    }
+
+    /// Time of the whole playthrough, including the completed level
+    pub fn total_time(&self, completed: &Level) -> Duration {
+        self.time + completed.final_time.unwrap_or_default()
+    }
+
+    pub fn final_score(&self, completed: &Level) -> i32 {
+        let secs = self.total_time(completed).as_secs().max(1);
+        (completed.score as f32 * 100.0 / secs as f32).round() as i32
+    }
+
+    /// Moves on to the next level carrying over the results of the completed one
+    pub fn advance(&mut self, completed: &Level) {
+        self.score = completed.score;
+        self.time += completed.final_time.unwrap_or_default();
+        self.lives = completed.player.lives;
+        if !self.last() {
+            self.current += 1;
+        }
+        if self.current >= self.unlocked {
+            self.unlocked = self.current + 1;
+            if let Err(e) = fs::write(Campaign::PROGRESS_FILE, self.unlocked.to_string()) {
+                println!("Error saving progress: {}", e);
+            }
+        }
+    }
+}
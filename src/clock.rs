@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+/// Simulation clock. It only moves forward when the level is updated, so every
+/// timer driven by it follows the game time instead of the wall clock.
+#[derive(Debug)]
+pub struct Clock {
+    now: Duration,
+    scale: f32,
+    paused: bool,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock { now: Duration::ZERO, scale: 1.0, paused: false }
+    }
+
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Game time elapsed since a previous reading of this clock
+    pub fn since(&self, earlier: Duration) -> Duration {
+        self.now.saturating_sub(earlier)
+    }
+
+    pub fn reset(&mut self) {
+        self.now = Duration::ZERO;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets how fast game time runs compared to real time, below 1.0 for slow motion
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    /// Advances the clock by the real time elapsed, returning the game time delta.
+    /// Negative or non-finite times leave the clock where it is.
+    pub fn advance(&mut self, elapsed: f32) -> f32 {
+        if self.paused {
+            return 0.0;
+        }
+        let delta = elapsed * self.scale;
+        match Duration::try_from_secs_f32(delta) {
+            Ok(duration) => {
+                self.now += duration;
+                delta
+            }
+            Err(_) => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_times_leave_the_clock_alone() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(0.5), 0.5);
+        for elapsed in [-0.1, f32::INFINITY, f32::NAN].iter() {
+            assert_eq!(clock.advance(*elapsed), 0.0);
+        }
+        assert_eq!(clock.now(), Duration::from_millis(500));
+    }
+}
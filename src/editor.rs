@@ -0,0 +1,258 @@
+use glam::Vec2;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use std::collections::HashSet;
+use std::fs;
+
+use crate::header::{Glyph, LevelHeader};
+use crate::input::Action;
+use crate::level::Level;
+use crate::render::Camera;
+use crate::tiled;
+
+/// Glyphs that can be painted, the first ones selected with the number keys in this order
+pub const BRUSHES: [Glyph; 19] = [
+    Glyph::TileLeft,
+    Glyph::TileCenter,
+    Glyph::TileRight,
+    Glyph::TileBottom,
+    Glyph::Platform,
+    Glyph::Decor,
+    Glyph::Enemy,
+    Glyph::Boss,
+    Glyph::Spawn,
+    Glyph::Trap,
+    Glyph::SlopeRight,
+    Glyph::SlopeLeft,
+    Glyph::RampRightLow,
+    Glyph::RampRightHigh,
+    Glyph::RampLeftHigh,
+    Glyph::RampLeftLow,
+    Glyph::Spikes,
+    Glyph::Lava,
+    Glyph::Checkpoint,
+];
+
+const BRUSH_KEYS: [Keycode; 10] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Num5,
+    Keycode::Num6,
+    Keycode::Num7,
+    Keycode::Num8,
+    Keycode::Num9,
+    Keycode::Num0,
+];
+
+/// Editor for text levels: the left mouse button paints the selected glyph,
+/// the right one erases and the movement actions pan the camera around.
+#[derive(Debug)]
+pub struct Editor {
+    file: String,
+    header_lines: Vec<String>,
+    header: LevelHeader,
+    grid: Vec<Vec<char>>,
+    brush: usize,
+    pub center: Vec2,
+    hover: Option<(usize, usize)>,
+    pub message: Option<String>,
+    // Whether there are edits not saved to the file yet
+    modified: bool,
+    // Level as currently painted, without the checks done when loading it
+    pub preview: Level,
+}
+
+impl Editor {
+    // Tiles per second
+    const PAN_SPEED: f32 = 20.0;
+
+    pub fn from_file(filename: &str) -> Result<Editor, String> {
+        if tiled::is_tiled(filename) {
+            return Err(format!("{}: Tiled maps can't be edited in the game", filename));
+        }
+        let level_str = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        let (header_lines, mut grid, grid_start) = LevelHeader::split(&level_str);
+        let header =
+            LevelHeader::parse(&header_lines).map_err(|e| format!("{}: {}", filename, e))?;
+
+        while grid.last().is_some_and(|line| line.trim().is_empty()) {
+            grid.pop();
+        }
+        // Short rows are padded so the grid is a rectangle
+        let width = grid.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let empty = Editor::glyph_char(&header, Glyph::Empty);
+        let grid = grid
+            .iter()
+            .map(|line| {
+                let mut row: Vec<char> = line.chars().collect();
+                row.resize(width, empty);
+                row
+            })
+            .collect();
+
+        // Files without a header are saved without one
+        let header_lines = if grid_start > 0 {
+            header_lines.iter().map(|line| line.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+
+        let mut editor = Editor {
+            file: filename.to_string(),
+            header_lines,
+            header,
+            grid,
+            brush: 0,
+            center: Vec2::ZERO,
+            hover: None,
+            message: None,
+            modified: false,
+            preview: Level::new(),
+        };
+        editor.rebuild();
+        editor.center = editor.preview.player.spawn;
+        Ok(editor)
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn brush(&self) -> Glyph {
+        BRUSHES[self.brush]
+    }
+
+    // Character standing for a glyph in the legend of the level
+    fn glyph_char(header: &LevelHeader, glyph: Glyph) -> char {
+        header.legend.iter().filter(|(_, g)| **g == glyph).map(|(c, _)| *c).min().unwrap_or(' ')
+    }
+
+    fn text(&self) -> String {
+        let mut lines = self.header_lines.clone();
+        if !lines.is_empty() {
+            lines.push(LevelHeader::SEPARATOR.to_string());
+        }
+        lines.extend(self.grid.iter().map(|row| row.iter().collect::<String>()));
+        lines.join("\n") + "\n"
+    }
+
+    fn rebuild(&mut self) {
+        let mut cells = Vec::new();
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                cells.push((x, y, self.header.glyph(*c).unwrap_or(Glyph::Empty)));
+            }
+        }
+        let width = self.grid.first().map_or(0, Vec::len);
+        self.preview = Level::from_cells(self.header.clone(), width, self.grid.len(), cells);
+    }
+
+    // Grid cell under a point of the world
+    fn cell(&self, point: Vec2) -> Option<(usize, usize)> {
+        let max = self.preview.max_bounds();
+        let (x, y) = ((point.x + max.x).floor(), (max.y - point.y).floor());
+        let in_grid = x >= 0.0 && y >= 0.0 && x < 2.0 * max.x && y < 2.0 * max.y;
+        in_grid.then_some((x as usize, y as usize))
+    }
+
+    /// Center of the cell under the mouse
+    pub fn hover_position(&self) -> Option<Vec2> {
+        let max = self.preview.max_bounds();
+        self.hover.map(|(x, y)| Vec2::new(x as f32 - max.x + 0.5, max.y - y as f32 - 0.5))
+    }
+
+    fn paint(&mut self, (x, y): (usize, usize), glyph: Glyph) {
+        let c = Editor::glyph_char(&self.header, glyph);
+        if self.grid[y][x] == c {
+            return;
+        }
+        // There is only one of these per level, so the previous one is moved
+        if [Glyph::Spawn, Glyph::Boss, Glyph::Trap].contains(&glyph) {
+            let empty = Editor::glyph_char(&self.header, Glyph::Empty);
+            for cell in self.grid.iter_mut().flatten().filter(|cell| **cell == c) {
+                *cell = empty;
+            }
+        }
+        self.grid[y][x] = c;
+        self.message = None;
+        self.modified = true;
+        self.rebuild();
+    }
+
+    pub fn modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn save(&mut self) {
+        self.message = Some(match fs::write(&self.file, self.text()) {
+            Ok(()) => {
+                self.modified = false;
+                format!("Saved {}", self.file)
+            }
+            Err(e) => format!("Error saving {}: {}", self.file, e),
+        });
+    }
+
+    /// Level to playtest, checked the same way as when it is loaded from the file
+    pub fn level(&self) -> Result<Level, String> {
+        Level::parse(&self.text()).map_err(|e| e.to_string())
+    }
+
+    pub fn handle_event(&mut self, event: &Event, camera: &Camera) {
+        let (x, y, button) = match *event {
+            Event::MouseButtonDown { x, y, mouse_btn, .. } => (x, y, Some(mouse_btn)),
+            Event::MouseMotion { x, y, mousestate, .. } if mousestate.left() => {
+                (x, y, Some(MouseButton::Left))
+            }
+            Event::MouseMotion { x, y, mousestate, .. } if mousestate.right() => {
+                (x, y, Some(MouseButton::Right))
+            }
+            Event::MouseMotion { x, y, .. } => (x, y, None),
+            Event::MouseWheel { y, .. } => {
+                let len = BRUSHES.len() as i32;
+                self.brush = (self.brush as i32 - y.signum()).rem_euclid(len) as usize;
+                return;
+            }
+            Event::KeyDown { keycode: Some(Keycode::S), keymod, .. }
+                if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+            {
+                self.save();
+                return;
+            }
+            Event::KeyDown { keycode: Some(key), .. } => {
+                if let Some(i) = BRUSH_KEYS.iter().position(|k| *k == key) {
+                    self.brush = i;
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        self.hover = self.cell(camera.to_world((x, y)));
+        if let Some(cell) = self.hover {
+            match button {
+                Some(MouseButton::Left) => self.paint(cell, self.brush()),
+                Some(MouseButton::Right) => self.paint(cell, Glyph::Empty),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn update(&mut self, actions: &HashSet<Action>, elapsed: f32) {
+        let mut direction = Vec2::ZERO;
+        for action in actions {
+            match action {
+                Action::MoveLeft => direction.x -= 1.0,
+                Action::MoveRight => direction.x += 1.0,
+                Action::MoveUp => direction.y += 1.0,
+                Action::Crouch => direction.y -= 1.0,
+                _ => {}
+            }
+        }
+        let max = self.preview.max_bounds();
+        self.center = (self.center + direction * Editor::PAN_SPEED * elapsed).clamp(-max, max);
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::Duration;
+
+use crate::level::LevelError;
+use crate::tiled;
+
+/// Kind of tile or entity a character of the level grid stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyph {
+    Empty,
+    TileLeft,
+    TileCenter,
+    TileRight,
+    TileBottom,
+    Platform,
+    SlopeRight,
+    SlopeLeft,
+    RampRightLow,
+    RampRightHigh,
+    RampLeftHigh,
+    RampLeftLow,
+    Spikes,
+    Lava,
+    Decor,
+    Enemy,
+    Boss,
+    Spawn,
+    Checkpoint,
+    Trap,
+}
+
+impl Glyph {
+    pub const ALL: [Glyph; 20] = [
+        Glyph::Empty,
+        Glyph::TileLeft,
+        Glyph::TileCenter,
+        Glyph::TileRight,
+        Glyph::TileBottom,
+        Glyph::Platform,
+        Glyph::SlopeRight,
+        Glyph::SlopeLeft,
+        Glyph::RampRightLow,
+        Glyph::RampRightHigh,
+        Glyph::RampLeftHigh,
+        Glyph::RampLeftLow,
+        Glyph::Spikes,
+        Glyph::Lava,
+        Glyph::Decor,
+        Glyph::Enemy,
+        Glyph::Boss,
+        Glyph::Spawn,
+        Glyph::Checkpoint,
+        Glyph::Trap,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Glyph::Empty => "empty",
+            Glyph::TileLeft => "tile_left",
+            Glyph::TileCenter => "tile_center",
+            Glyph::TileRight => "tile_right",
+            Glyph::TileBottom => "tile_bottom",
+            Glyph::Platform => "platform",
+            Glyph::SlopeRight => "slope_right",
+            Glyph::SlopeLeft => "slope_left",
+            Glyph::RampRightLow => "ramp_right_low",
+            Glyph::RampRightHigh => "ramp_right_high",
+            Glyph::RampLeftHigh => "ramp_left_high",
+            Glyph::RampLeftLow => "ramp_left_low",
+            Glyph::Spikes => "spikes",
+            Glyph::Lava => "lava",
+            Glyph::Decor => "decor",
+            Glyph::Enemy => "enemy",
+            Glyph::Boss => "boss",
+            Glyph::Spawn => "spawn",
+            Glyph::Checkpoint => "checkpoint",
+            Glyph::Trap => "trap",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Glyph> {
+        Glyph::ALL.iter().copied().find(|g| g.name() == name)
+    }
+}
+
+/// Moves the player can only do in the levels that allow them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ability {
+    /// Jumping a second time in the air
+    DoubleJump,
+    /// Sliding slowly down the walls pushed against
+    WallSlide,
+    /// Jumping away from a wall while in the air
+    WallJump,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 3] = [Ability::DoubleJump, Ability::WallSlide, Ability::WallJump];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ability::DoubleJump => "double_jump",
+            Ability::WallSlide => "wall_slide",
+            Ability::WallJump => "wall_jump",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Ability> {
+        Ability::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// Waypoints followed by a moving platform, as the grid cells of its left end
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlatformPath {
+    pub waypoints: Vec<(usize, usize)>,
+    /// Whether the platform goes from the last waypoint back to the first one
+    /// instead of retracing its path
+    pub looping: bool,
+}
+
+/// Optional metadata at the top of a level file, ended by a `---` line.
+///
+/// Each line is a `key: value` pair. Times are given in seconds, possibly fractional, or
+/// as `m:ss`, backgrounds as a comma separated list of images from back to front and
+/// legend entries as `legend: <char> = <glyph>` and abilities as a comma separated list.
+/// Every `moving_platform` line adds a platform following the `column,row` waypoints
+/// listed, optionally ended by `loop`.
+#[derive(Debug, Clone)]
+pub struct LevelHeader {
+    pub name: Option<String>,
+    pub music: Option<String>,
+    pub background: Vec<String>,
+    pub time_limit: Option<Duration>,
+    pub gravity: Option<f32>,
+    pub par_time: Option<Duration>,
+    pub coyote_time: Option<Duration>,
+    pub jump_buffer: Option<Duration>,
+    pub abilities: HashSet<Ability>,
+    pub legend: HashMap<char, Glyph>,
+    pub platforms: Vec<PlatformPath>,
+}
+
+fn parse_time(value: &str) -> Option<Duration> {
+    let secs = match value.split_once(':') {
+        Some((m, s)) => m.trim().parse::<u64>().ok()? * 60 + s.trim().parse::<u64>().ok()?,
+        None => return Duration::try_from_secs_f32(value.parse().ok()?).ok(),
+    };
+    Some(Duration::from_secs(secs))
+}
+
+// Grid cell given as `column,row`, counted from 1 like the positions in errors
+fn parse_cell(value: &str) -> Option<(usize, usize)> {
+    let (x, y) = value.split_once(',')?;
+    let (x, y) = (x.trim().parse::<usize>().ok()?, y.trim().parse::<usize>().ok()?);
+    Some((x.checked_sub(1)?, y.checked_sub(1)?))
+}
+
+impl LevelHeader {
+    pub const SEPARATOR: &'static str = "---";
+
+    pub fn new() -> LevelHeader {
+        let legend = [
+            ('.', Glyph::Empty),
+            ('[', Glyph::TileLeft),
+            ('=', Glyph::TileCenter),
+            (']', Glyph::TileRight),
+            ('#', Glyph::TileBottom),
+            ('_', Glyph::Platform),
+            ('/', Glyph::SlopeRight),
+            ('\\', Glyph::SlopeLeft),
+            ('r', Glyph::RampRightLow),
+            ('R', Glyph::RampRightHigh),
+            ('L', Glyph::RampLeftHigh),
+            ('l', Glyph::RampLeftLow),
+            ('^', Glyph::Spikes),
+            ('~', Glyph::Lava),
+            ('D', Glyph::Decor),
+            ('E', Glyph::Enemy),
+            ('M', Glyph::Boss),
+            ('S', Glyph::Spawn),
+            ('C', Glyph::Checkpoint),
+            ('@', Glyph::Trap),
+        ];
+        LevelHeader {
+            name: None,
+            music: None,
+            background: Vec::new(),
+            time_limit: None,
+            gravity: None,
+            par_time: None,
+            coyote_time: None,
+            jump_buffer: None,
+            abilities: HashSet::new(),
+            legend: legend.iter().copied().collect(),
+            platforms: Vec::new(),
+        }
+    }
+
+    /// Splits the contents of a level file into header and grid lines, along with
+    /// the index of the first grid line. Files without a separator line are made
+    /// of the grid only.
+    pub fn split(level_str: &str) -> (Vec<&str>, Vec<&str>, usize) {
+        let lines: Vec<&str> = level_str.lines().collect();
+        match lines.iter().position(|line| line.trim() == LevelHeader::SEPARATOR) {
+            Some(i) => (lines[..i].to_vec(), lines[i + 1..].to_vec(), i + 1),
+            None => (Vec::new(), lines, 0),
+        }
+    }
+
+    pub fn parse(lines: &[&str]) -> Result<LevelHeader, LevelError> {
+        let mut header = LevelHeader::new();
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| LevelError::Header { line: i + 1, message: msg.to_string() };
+            let (key, value) =
+                line.split_once(':').ok_or_else(|| error("expected 'key: value'"))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => header.name = Some(value.to_string()),
+                "music" => header.music = Some(value.to_string()),
+                "background" => {
+                    header.background = value.split(',').map(|s| s.trim().to_string()).collect()
+                }
+                "time_limit" => {
+                    header.time_limit =
+                        Some(parse_time(value).ok_or_else(|| error("invalid time"))?)
+                }
+                "par_time" => {
+                    header.par_time = Some(parse_time(value).ok_or_else(|| error("invalid time"))?)
+                }
+                "coyote_time" => {
+                    header.coyote_time =
+                        Some(parse_time(value).ok_or_else(|| error("invalid time"))?)
+                }
+                "jump_buffer" => {
+                    header.jump_buffer =
+                        Some(parse_time(value).ok_or_else(|| error("invalid time"))?)
+                }
+                "gravity" => {
+                    header.gravity = Some(value.parse().map_err(|_| error("invalid gravity"))?)
+                }
+                "abilities" => {
+                    header.abilities = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| {
+                            Ability::from_name(name)
+                                .ok_or_else(|| error(&format!("unknown ability '{}'", name)))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "legend" => {
+                    let (c, glyph) = value
+                        .split_once('=')
+                        .ok_or_else(|| error("expected 'legend: <char> = <glyph>'"))?;
+                    let mut chars = c.trim().chars();
+                    let c = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => return Err(error("legend key must be a single character")),
+                    };
+                    let glyph = Glyph::from_name(glyph.trim())
+                        .ok_or_else(|| error(&format!("unknown glyph '{}'", glyph.trim())))?;
+                    header.legend.insert(c, glyph);
+                }
+                "moving_platform" => {
+                    let mut tokens: Vec<&str> = value.split_whitespace().collect();
+                    let looping = tokens.last() == Some(&"loop");
+                    if looping {
+                        tokens.pop();
+                    }
+                    let waypoints = tokens
+                        .iter()
+                        .map(|t| {
+                            parse_cell(t).ok_or_else(|| {
+                                error(&format!("invalid waypoint '{}', expected column,row", t))
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if waypoints.len() < 2 {
+                        return Err(error("moving platform needs at least two waypoints"));
+                    }
+                    header.platforms.push(PlatformPath { waypoints, looping });
+                }
+                other => return Err(error(&format!("unknown key '{}'", other))),
+            }
+        }
+        Ok(header)
+    }
+
+    /// Reads only the header of a level file
+    pub fn from_file(filename: &str) -> Result<LevelHeader, LevelError> {
+        if tiled::is_tiled(filename) {
+            return Ok(tiled::from_file(filename)?.header);
+        }
+        let level_str = fs::read_to_string(filename)?;
+        let (header, _, _) = LevelHeader::split(&level_str);
+        LevelHeader::parse(&header)
+    }
+
+    pub fn glyph(&self, c: char) -> Option<Glyph> {
+        self.legend.get(&c).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(lines: &[&str]) -> (usize, String) {
+        match LevelHeader::parse(lines) {
+            Err(LevelError::Header { line, message }) => (line, message),
+            other => panic!("expected a header error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_keys() {
+        let header = LevelHeader::parse(&[
+            "# comment",
+            "name: Jungle",
+            "music: assets/jungle.ogg",
+            "background: sky.png, hills.png",
+            "time_limit: 2:05",
+            "par_time: 45",
+            "coyote_time: 0.25",
+            "gravity: -20",
+            "",
+            "abilities: double_jump, wall_jump",
+            "legend: x = spikes",
+            "moving_platform: 2,3 6,3 loop",
+        ])
+        .unwrap();
+        assert_eq!(header.name.as_deref(), Some("Jungle"));
+        assert_eq!(header.music.as_deref(), Some("assets/jungle.ogg"));
+        assert_eq!(header.background, vec!["sky.png", "hills.png"]);
+        assert_eq!(header.time_limit, Some(Duration::from_secs(125)));
+        assert_eq!(header.par_time, Some(Duration::from_secs(45)));
+        assert_eq!(header.coyote_time, Some(Duration::from_millis(250)));
+        assert_eq!(header.jump_buffer, None);
+        assert_eq!(header.gravity, Some(-20.0));
+        assert!(header.abilities.contains(&Ability::DoubleJump));
+        assert!(!header.abilities.contains(&Ability::WallSlide));
+        assert_eq!(header.glyph('x'), Some(Glyph::Spikes));
+        assert_eq!(header.glyph('['), Some(Glyph::TileLeft));
+        assert_eq!(
+            header.platforms,
+            vec![PlatformPath { waypoints: vec![(1, 2), (5, 2)], looping: true }]
+        );
+    }
+
+    #[test]
+    fn splits_header_from_grid() {
+        let (header, grid, start) = LevelHeader::split("name: a\n---\n...\n");
+        assert_eq!((header, grid, start), (vec!["name: a"], vec!["..."], 2));
+        let (header, grid, start) = LevelHeader::split("...\n...\n");
+        assert_eq!((header.len(), grid.len(), start), (0, 2, 0));
+    }
+
+    #[test]
+    fn invalid_times() {
+        for time in ["soon", "1:xx", "-3", "1:"].iter() {
+            let line = format!("time_limit: {}", time);
+            assert_eq!(error_message(&[&line]), (1, "invalid time".to_string()), "{}", time);
+        }
+    }
+
+    #[test]
+    fn bad_keys_and_values() {
+        assert_eq!(error_message(&["name: a", "speed: 3"]), (2, "unknown key 'speed'".into()));
+        assert_eq!(error_message(&["no colon"]), (1, "expected 'key: value'".into()));
+        assert_eq!(error_message(&["gravity: down"]), (1, "invalid gravity".into()));
+        assert_eq!(error_message(&["legend: x = lake"]), (1, "unknown glyph 'lake'".into()));
+        assert_eq!(
+            error_message(&["legend: xy = lava"]),
+            (1, "legend key must be a single character".into())
+        );
+        assert_eq!(error_message(&["abilities: fly"]), (1, "unknown ability 'fly'".into()));
+        assert_eq!(
+            error_message(&["moving_platform: 1,1"]),
+            (1, "moving platform needs at least two waypoints".into())
+        );
+        assert_eq!(
+            error_message(&["moving_platform: 1,1 0,2"]),
+            (1, "invalid waypoint '0,2', expected column,row".into())
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::fs;
+use std::iter;
+
+use crate::input::Action;
+use crate::level::Level;
+use crate::replay::Replay;
+use crate::sound::SoundEffect;
+use crate::FIXED_TIMESTEP;
+
+/// Number of frames simulated when no input script is provided
+const IDLE_FRAMES: u32 = 600;
+
+/// Scripted runs always use the same seed so their outcome is reproducible
+const SCRIPT_SEED: u64 = 0;
+
+/// Sequence of input steps, each one holding a set of actions for a number of frames.
+///
+/// Scripts are plain text files with one step per line in the form
+/// `<frames> [action ...]`, where actions are `MoveLeft`, `MoveRight`, `Jump`, `Crouch`,
+/// `Sprint` and `Dash`.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+pub struct Script {
+    steps: Vec<(u32, HashSet<Action>)>,
+}
+
+impl Script {
+    pub fn idle(frames: u32) -> Script {
+        Script { steps: vec![(frames, HashSet::new())] }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Script, String> {
+        let script_str = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+
+        let mut script = Script::default();
+        for (i, line) in script_str.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let frames = tokens
+                .next()
+                .and_then(|t| t.parse::<u32>().ok())
+                .ok_or_else(|| format!("{}:{}: invalid frame count", filename, i + 1))?;
+            let actions = tokens
+                .map(|t| {
+                    Action::from_name(t)
+                        .ok_or_else(|| format!("{}:{}: unknown action '{}'", filename, i + 1, t))
+                })
+                .collect::<Result<_, _>>()?;
+            script.steps.push((frames, actions));
+        }
+
+        Ok(script)
+    }
+
+    /// Expands the script into the set of active actions for every frame
+    pub fn frames(&self) -> impl Iterator<Item = &HashSet<Action>> {
+        self.steps.iter().flat_map(|(frames, actions)| iter::repeat_n(actions, *frames as usize))
+    }
+}
+
+/// Runs a level without window, audio or fonts, feeding it the scripted input
+/// at a fixed timestep and printing the outcome once the run is over.
+pub fn run(level_file: &str, script_file: Option<&str>) -> Result<(), String> {
+    let script = match script_file {
+        Some(filename) => Script::from_file(filename)?,
+        None => Script::idle(IDLE_FRAMES),
+    };
+
+    let mut level = Level::from_file(level_file).map_err(|e| format!("{}: {}", level_file, e))?;
+    level.reseed(SCRIPT_SEED);
+    simulate(level, script.frames().map(|actions| (FIXED_TIMESTEP, actions)));
+    Ok(())
+}
+
+/// Plays a recorded run back without window, audio or fonts
+pub fn replay(replay: &Replay) -> Result<(), String> {
+    let mut level =
+        Level::from_file(&replay.level).map_err(|e| format!("{}: {}", replay.level, e))?;
+    level.reseed(replay.seed);
+    simulate(level, replay.frames.iter().map(|(elapsed, actions)| (*elapsed, actions)));
+    Ok(())
+}
+
+fn simulate<'a>(mut level: Level, frames: impl Iterator<Item = (f32, &'a HashSet<Action>)>) {
+    level.start();
+
+    let mut count = 0;
+    for (elapsed, actions) in frames {
+        if level.player.game_over() || level.monkey.dead() {
+            break;
+        }
+        let mut sounds = Vec::<SoundEffect>::new();
+        level.update(elapsed, actions, &mut sounds);
+        count += 1;
+    }
+
+    let outcome = if level.monkey.dead() {
+        String::from("Victory")
+    } else if let Some(cause) = level.player.death_cause.filter(|_| level.player.lives == 0) {
+        format!("Dead ({:?})", cause)
+    } else {
+        String::from("Incomplete")
+    };
+
+    println!("Outcome: {}", outcome);
+    println!("Score: {}", level.score);
+    println!("Lives: {}", level.player.lives);
+    println!("Time: {:.2}s ({} frames)", level.clock.now().as_secs_f32(), count);
+    println!("Seed: {}", level.seed());
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::GameControllerSubsystem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    Jump,
+    Crouch,
+    Sprint,
+    Dash,
+    Pause,
+    Restart,
+    Edit,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::Jump,
+        Action::Crouch,
+        Action::Sprint,
+        Action::Dash,
+        Action::Pause,
+        Action::Restart,
+        Action::Edit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "MoveLeft",
+            Action::MoveRight => "MoveRight",
+            Action::MoveUp => "MoveUp",
+            Action::Jump => "Jump",
+            Action::Crouch => "Crouch",
+            Action::Sprint => "Sprint",
+            Action::Dash => "Dash",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Edit => "Edit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// Mapping table from keyboard keys to game actions
+#[derive(Debug)]
+pub struct KeyBindings {
+    bindings: HashMap<Keycode, Action>,
+}
+
+impl KeyBindings {
+    pub fn new() -> KeyBindings {
+        let bindings = [
+            (Keycode::Left, Action::MoveLeft),
+            (Keycode::Right, Action::MoveRight),
+            (Keycode::Up, Action::MoveUp),
+            (Keycode::Space, Action::Jump),
+            (Keycode::Down, Action::Crouch),
+            (Keycode::LShift, Action::Sprint),
+            (Keycode::X, Action::Dash),
+            (Keycode::P, Action::Pause),
+            (Keycode::Escape, Action::Pause),
+            (Keycode::R, Action::Restart),
+            (Keycode::Tab, Action::Edit),
+        ];
+        KeyBindings { bindings: bindings.iter().copied().collect() }
+    }
+
+    /// Loads the bindings from a file with one `Action = Key[, Key...]` entry per line.
+    /// Actions not present in the file keep their default keys.
+    pub fn from_file(filename: &str) -> Result<KeyBindings, String> {
+        let mut key_bindings = KeyBindings::new();
+        let bindings_str = match fs::read_to_string(filename) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(key_bindings),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        for (i, line) in bindings_str.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, keys) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{}:{}: expected 'Action = Key'", filename, i + 1))?;
+            let action = Action::from_name(name.trim()).ok_or_else(|| {
+                format!("{}:{}: unknown action '{}'", filename, i + 1, name.trim())
+            })?;
+
+            key_bindings.bindings.retain(|_, a| *a != action);
+            for key in keys.split(',').map(str::trim) {
+                let keycode = Keycode::from_name(key)
+                    .ok_or_else(|| format!("{}:{}: unknown key '{}'", filename, i + 1, key))?;
+                key_bindings.bindings.insert(keycode, action);
+            }
+        }
+
+        Ok(key_bindings)
+    }
+
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    pub fn actions(&self, keys: impl Iterator<Item = Keycode>) -> HashSet<Action> {
+        keys.filter_map(|k| self.action(k)).collect()
+    }
+}
+
+/// Connected game controllers, opened and closed as they are plugged in and out
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+}
+
+impl Controllers {
+    // Axis values inside this range are ignored to account for stick drift
+    const DEADZONE: i16 = 8000;
+
+    const BUTTONS: [(Button, Action); 9] = [
+        (Button::DPadLeft, Action::MoveLeft),
+        (Button::DPadRight, Action::MoveRight),
+        (Button::DPadUp, Action::MoveUp),
+        (Button::DPadDown, Action::Crouch),
+        (Button::A, Action::Jump),
+        (Button::B, Action::Sprint),
+        (Button::X, Action::Dash),
+        (Button::Start, Action::Pause),
+        (Button::Back, Action::Restart),
+    ];
+
+    pub fn new(subsystem: GameControllerSubsystem) -> Controllers {
+        Controllers { subsystem, controllers: HashMap::new() }
+    }
+
+    pub fn action(button: Button) -> Option<Action> {
+        Controllers::BUTTONS.iter().find(|(b, _)| *b == button).map(|(_, a)| *a)
+    }
+
+    /// Handles hot-plug events. SDL also reports controllers connected at startup as added.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    println!("Controller connected: {}", controller.name());
+                    self.controllers.insert(controller.instance_id(), controller);
+                }
+                Err(e) => println!("Error opening controller {}: {}", which, e),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.controllers.remove(&which) {
+                    println!("Controller disconnected: {}", controller.name());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn actions(&self) -> HashSet<Action> {
+        let mut actions = HashSet::new();
+        for controller in self.controllers.values() {
+            for (button, action) in &Controllers::BUTTONS {
+                if controller.button(*button) {
+                    actions.insert(*action);
+                }
+            }
+
+            let x = controller.axis(Axis::LeftX);
+            if x < -Controllers::DEADZONE {
+                actions.insert(Action::MoveLeft);
+            } else if x > Controllers::DEADZONE {
+                actions.insert(Action::MoveRight);
+            }
+            let y = controller.axis(Axis::LeftY);
+            if y < -Controllers::DEADZONE {
+                actions.insert(Action::MoveUp);
+            } else if y > Controllers::DEADZONE {
+                actions.insert(Action::Crouch);
+            }
+        }
+        actions
+    }
+}
@@ -1,41 +1,102 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::vec::Vec;
 
 use glam::{const_vec2, Vec2};
-use sdl2::keyboard::Keycode;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
+use crate::clock::Clock;
+use crate::header::{Glyph, LevelHeader};
+use crate::input::Action;
 use crate::monkey::Monkey;
 use crate::physics;
-use crate::player::Player;
+use crate::platform::MovingPlatform;
+use crate::player::{DeathCause, Player};
 use crate::sound::SoundEffect;
+use crate::tiled;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Header { line: usize, message: String },
+    UnknownGlyph { line: usize, column: usize, glyph: char },
+    RaggedRow { line: usize, length: usize, expected: usize },
+    Missing(Glyph),
+    Duplicate { glyph: Glyph, line: usize, column: usize, first: (usize, usize) },
+    PlatformOutside { column: usize, row: usize },
+    Tiled(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(e) => write!(f, "{}", e),
+            LevelError::Header { line, message } => write!(f, "line {}: {}", line, message),
+            LevelError::Tiled(message) => write!(f, "{}", message),
+            LevelError::UnknownGlyph { line, column, glyph } => {
+                write!(f, "line {}, column {}: unknown glyph '{}'", line, column, glyph)
+            }
+            LevelError::RaggedRow { line, length, expected } => write!(
+                f,
+                "line {}: row has {} columns, expected {} like the first row",
+                line, length, expected
+            ),
+            LevelError::Missing(glyph) => write!(f, "no {} in level", glyph.name()),
+            LevelError::Duplicate { glyph, line, column, first } => write!(
+                f,
+                "line {}, column {}: duplicate {}, first one at line {}, column {}",
+                line,
+                column,
+                glyph.name(),
+                first.0,
+                first.1
+            ),
+            LevelError::PlatformOutside { column, row } => {
+                write!(f, "moving platform waypoint {},{} is outside the grid", column, row)
+            }
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(e: io::Error) -> LevelError {
+        LevelError::Io(e)
+    }
+}
 
 #[derive(Debug)]
 pub struct Enemy {
     pub spawn: Vec2,
     pub position: Vec2,
+    pub previous_position: Vec2,
     pub sides: Vec2,
     pub velocity: Vec2,
     health: i32,
     pub sprite: (i32, i32, u32, u32),
-    timer: Instant,
 }
 
 impl Enemy {
     const INITIAL_HEALTH: i32 = 1;
     const INITIAL_VELOCITY: Vec2 = const_vec2!([-5.0, 0.0]);
+    // Highest change in the ground followed while walking
+    const STEP_HEIGHT: f32 = 0.5;
 
     pub fn new() -> Enemy {
         Enemy {
             spawn: Vec2::ZERO,
             position: Vec2::ZERO,
+            previous_position: Vec2::ZERO,
             sides: Vec2::new(1.5, 3.0),
             velocity: Enemy::INITIAL_VELOCITY,
             health: Enemy::INITIAL_HEALTH,
             sprite: (0, 0, 128, 256),
-            timer: Instant::now(),
         }
     }
 
@@ -59,29 +120,55 @@ impl Enemy {
         self.sides - Vec2::new(0.5, 0.5)
     }
 
-    pub fn update(&mut self, elapsed: f32, tiles: &Vec<Tile>) {
-        let displacement = self.velocity * elapsed;
+    /// Puts the enemy back where it started, walking the same way
+    pub fn reset(&mut self) {
+        self.position = self.spawn;
+        self.previous_position = self.spawn;
+        self.velocity = Enemy::INITIAL_VELOCITY;
+    }
 
-        let mut x_collision = false;
-        let mut y_collision = false;
-        for t in tiles {
-            if physics::collides(self.position + displacement, self.sides, t.position, t.sides) {
-                x_collision = true;
-                break;
-            }
+    /// Whether the enemy would have ground to walk on at a position. Slopes are looked for
+    /// a tile lower, as the ground ahead goes down a whole tile on the steep ones.
+    pub fn floor_at(&self, position: Vec2, tiles: &TileGrid, platforms: &[MovingPlatform]) -> bool {
+        let probe = position + Vec2::new(0.0, -0.2);
+        let slope_probe = probe - Vec2::Y * Tile::SIDE;
+        tiles.near(probe, self.sides + Vec2::Y * 2.0 * Tile::SIDE).any(|t| {
+            physics::collides(probe, self.sides, t.position, t.sides)
+                || t.sloped() && physics::collides(slope_probe, self.sides, t.position, t.sides)
+        }) || platforms.iter().any(|p| physics::collides(probe, self.sides, p.position, p.sides))
+    }
 
-            let future_y_pos =
-                self.position + (Vec2::X * displacement.signum()) + Vec2::new(0.0, -0.2);
-            y_collision |= physics::collides(future_y_pos, self.sides, t.position, t.sides);
-        }
-        if x_collision || !y_collision {
+    pub fn update(
+        &mut self,
+        elapsed: f32,
+        clock: &Clock,
+        tiles: &TileGrid,
+        platforms: &[MovingPlatform],
+    ) {
+        let displacement = self.velocity * elapsed;
+
+        // Turns around at walls and ledges, one-way platforms only count as floor and low
+        // steps like the tiles at the top of slopes are walked onto
+        let next_position = self.position + displacement;
+        let feet = next_position.y - self.sides.y / 2.0;
+        let x_collision = tiles
+            .near(next_position, self.sides)
+            .filter(|t| t.solid() && t.top(next_position.x) > feet + Enemy::STEP_HEIGHT)
+            .any(|t| physics::collides(next_position, self.sides, t.position, t.sides));
+        let ahead = self.position + Vec2::X * displacement.signum();
+        if x_collision || !self.floor_at(ahead, tiles, platforms) {
             self.velocity = -self.velocity
         }
         let displacement = self.velocity * elapsed;
         self.position += displacement;
+        // Walks up and down slopes with its feet on the ground
+        let step = Enemy::STEP_HEIGHT;
+        if let Some((top, _)) = tiles.ground(self.position, self.sides, step, step, |_| true) {
+            self.position.y = top + self.sides.y / 2.0;
+        }
 
         if self.velocity.x.abs() > 0.0 {
-            let col: i32 = (self.timer.elapsed().as_millis() as i32 / 160 % 4) * 128;
+            let col: i32 = (clock.now().as_millis() as i32 / 160 % 4) * 128;
             self.sprite = (col, 0, 128, 256);
         } else {
             self.sprite = (0, 0, 128, 256);
@@ -89,11 +176,26 @@ impl Enemy {
     }
 }
 
+/// How a tile blocks the objects moving against it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileKind {
+    Solid,
+    /// Only stops what lands on it from above, so it can be jumped up through
+    OneWay,
+    /// Floor rising from one side to the other, with the heights of its top at the left
+    /// and right edges given as fractions of the tile side
+    Slope {
+        left: f32,
+        right: f32,
+    },
+}
+
 #[derive(Debug)]
 pub struct Tile {
     pub position: Vec2,
     pub sides: Vec2,
     pub sprite: (i32, i32, u32, u32),
+    pub kind: TileKind,
 }
 
 impl Tile {
@@ -102,39 +204,203 @@ impl Tile {
     const RIGHT: (i32, i32, u32, u32) = (64, 0, 64, 64);
     const CENTER: (i32, i32, u32, u32) = (128, 0, 64, 64);
     const BOTTOM: (i32, i32, u32, u32) = (192, 0, 64, 64);
+    // Top halves of the tiles, for the thin platforms
+    pub const PLATFORM_LEFT: (i32, i32, u32, u32) = (0, 0, 64, 32);
+    pub const PLATFORM: (i32, i32, u32, u32) = (128, 0, 64, 32);
+    pub const PLATFORM_RIGHT: (i32, i32, u32, u32) = (64, 0, 64, 32);
+
+    // Height gained by a ramp over one tile, tan(22.5°) for a gentler angle than the slopes
+    const RAMP_RISE: f32 = 0.414;
+
+    fn slope(position: Vec2, left: f32, right: f32) -> Tile {
+        Tile {
+            position,
+            sides: Vec2::new(Tile::SIDE, Tile::SIDE),
+            sprite: Tile::CENTER,
+            kind: TileKind::Slope { left, right },
+        }
+    }
+
+    /// Whether the tile blocks from every side, making walls as well as floors
+    pub fn solid(&self) -> bool {
+        self.kind == TileKind::Solid
+    }
+
+    /// Whether the top of the tile is a slope or ramp instead of flat
+    pub fn sloped(&self) -> bool {
+        matches!(self.kind, TileKind::Slope { .. })
+    }
+
+    /// Height of the top of the tile at a point along it, which follows the slope if any
+    pub fn top(&self, x: f32) -> f32 {
+        let bottom = self.position.y - self.sides.y / 2.0;
+        match self.kind {
+            TileKind::Slope { left, right } => {
+                let along = ((x - self.position.x) / self.sides.x + 0.5).clamp(0.0, 1.0);
+                bottom + (left + (right - left) * along) * self.sides.y
+            }
+            _ => bottom + self.sides.y,
+        }
+    }
+}
+
+/// Tiles bucketed by the integer cell of their center, so collision checks only look
+/// at the tiles around an object instead of the whole level
+#[derive(Debug, Default)]
+pub struct TileGrid {
+    tiles: Vec<Tile>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl TileGrid {
+    fn cell(point: Vec2) -> (i32, i32) {
+        (point.x.floor() as i32, point.y.floor() as i32)
+    }
+
+    pub fn push(&mut self, tile: Tile) {
+        self.cells.entry(TileGrid::cell(tile.position)).or_default().push(self.tiles.len());
+        self.tiles.push(tile);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tile> {
+        self.tiles.iter()
+    }
+
+    /// Tiles that may overlap a rectangle, as long as no tile is larger than `Tile::SIDE`
+    pub fn near(&self, position: Vec2, sides: Vec2) -> impl Iterator<Item = &Tile> {
+        let reach = (sides + Vec2::splat(Tile::SIDE)) / 2.0;
+        let (min, max) = (TileGrid::cell(position - reach), TileGrid::cell(position + reach));
+        (min.0..=max.0)
+            .flat_map(move |x| (min.1..=max.1).map(move |y| (x, y)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flatten()
+            .map(move |&i| &self.tiles[i])
+    }
+
+    /// Highest top of the tiles under a rectangle, from `below` under its bottom up to
+    /// `above` over it. Slopes count where they are highest under the rectangle.
+    pub fn ground(
+        &self,
+        position: Vec2,
+        sides: Vec2,
+        below: f32,
+        above: f32,
+        filter: impl Fn(&Tile) -> bool,
+    ) -> Option<(f32, TileKind)> {
+        let bottom = position.y - sides.y / 2.0;
+        let (left, right) = (position.x - sides.x / 2.0, position.x + sides.x / 2.0);
+        self.near(position, sides + Vec2::Y * 2.0 * below.max(above))
+            .filter(|t| {
+                (t.position.x - position.x).abs() < (sides.x + t.sides.x) / 2.0 && filter(t)
+            })
+            .map(|t| {
+                let (min, max) = (t.position.x - t.sides.x / 2.0, t.position.x + t.sides.x / 2.0);
+                (t.top(left.max(min)).max(t.top(right.min(max))), t.kind)
+            })
+            .filter(|(top, _)| *top >= bottom - below && *top <= bottom + above)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HazardKind {
+    Spikes,
+    Lava,
+}
+
+/// Tile that kills the player on touch. Hazards are not solid, so the player falls into
+/// them and enemies turn around at their edges like at ledges.
+#[derive(Debug)]
+pub struct Hazard {
+    pub position: Vec2,
+    pub sides: Vec2,
+    pub kind: HazardKind,
+}
+
+impl Hazard {
+    pub fn new(position: Vec2, kind: HazardKind) -> Hazard {
+        let height = match kind {
+            HazardKind::Spikes => 0.5,
+            HazardKind::Lava => 0.75,
+        };
+        let sides = Vec2::new(Tile::SIDE, height);
+        Hazard { position: Level::offset(position, sides.y), sides, kind }
+    }
+
+    pub fn death_cause(&self) -> DeathCause {
+        match self.kind {
+            HazardKind::Spikes => DeathCause::Spikes,
+            HazardKind::Lava => DeathCause::Lava,
+        }
+    }
+
+    pub fn sound(&self) -> SoundEffect {
+        match self.kind {
+            HazardKind::Spikes => SoundEffect::Spikes,
+            HazardKind::Lava => SoundEffect::Lava,
+        }
+    }
+}
+
+/// Flag that moves the point where the player respawns to it when touched
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub position: Vec2,
+    pub sides: Vec2,
+    /// Position of the player respawning here
+    pub respawn: Vec2,
+    pub reached: bool,
 }
 
 #[derive(Debug)]
 pub struct Level {
     started: bool,
     bounds: Vec2,
-    pub tiles: Vec<Tile>,
+    pub tiles: TileGrid,
     pub decor: Vec<Tile>,
+    pub hazards: Vec<Hazard>,
+    pub checkpoints: Vec<Checkpoint>,
+    pub platforms: Vec<MovingPlatform>,
     pub enemies: Vec<Enemy>,
     pub player: Player,
     pub monkey: Monkey,
     pub trapped: bool,
     trap: Vec2,
     pub score: i32,
-    pub timer: Instant,
+    pub header: LevelHeader,
+    pub clock: Clock,
     pub final_time: Option<Duration>,
+    seed: u64,
+    rng: StdRng,
 }
 
 impl Level {
+    const PAR_BONUS: i32 = 250;
+    // Horizontal distance from the respawn point, in tiles, within which enemies are put
+    // back where they started and bananas are cleared when the player respawns
+    const RESPAWN_CLEARANCE: f32 = 16.0;
+
     pub fn new() -> Level {
+        let seed = rand::random();
         Level {
             started: false,
             bounds: Vec2::ZERO,
-            tiles: Vec::new(),
+            tiles: TileGrid::default(),
             decor: Vec::new(),
+            hazards: Vec::new(),
+            checkpoints: Vec::new(),
+            platforms: Vec::new(),
             enemies: Vec::new(),
             monkey: Monkey::new(),
             player: Player::new(),
             trapped: false,
             trap: Vec2::ZERO,
             score: 0,
-            timer: Instant::now(),
+            header: LevelHeader::new(),
+            clock: Clock::new(),
             final_time: None,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
@@ -146,44 +412,149 @@ impl Level {
         Vec2::new(self.bounds.x / 2.0, self.bounds.y / 2.0)
     }
 
+    pub fn trap(&self) -> Vec2 {
+        self.trap
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        self.header.gravity.map(|g| Vec2::new(0.0, g)).unwrap_or(physics::GRAVITY)
+    }
+
+    /// Time left before the player runs out of time, for levels with a limit
+    pub fn time_left(&self) -> Option<Duration> {
+        self.header.time_limit.map(|limit| limit.saturating_sub(self.clock.now()))
+    }
+
     pub fn started(&self) -> bool {
         self.started
     }
 
     pub fn start(&mut self) {
         self.started = true;
-        self.timer = Instant::now();
+        self.clock.reset();
     }
 
-    pub fn final_score(&self) -> i32 {
-        if let Some(time) = self.final_time {
-            (self.score as f32 * 100.0 / time.as_secs() as f32).round() as i32
-        } else {
-            0
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Keeps the positions before an update, so rendering can blend them with the new ones
+    fn store_positions(&mut self) {
+        self.player.previous_position = self.player.position;
+        self.monkey.previous_position = self.monkey.position;
+        for e in &mut self.enemies {
+            e.previous_position = e.position;
+        }
+        for b in &mut self.monkey.bananas {
+            b.previous_position = b.position;
+        }
+        for p in &mut self.platforms {
+            p.previous_position = p.position;
+        }
+    }
+
+    /// Brings the player back at the last checkpoint reached or the spawn point. Enemies
+    /// killed stay dead, so they can't be farmed for points.
+    fn respawn(&mut self) {
+        self.player.respawn();
+        self.trapped = false;
+        let spawn = self.player.spawn;
+        let near = |position: Vec2| (position.x - spawn.x).abs() < Level::RESPAWN_CLEARANCE;
+        for e in self.enemies.iter_mut().filter(|e| !e.dead()) {
+            if near(e.position) || near(e.spawn) {
+                e.reset();
+            }
+        }
+        self.monkey.bananas.retain(|b| !near(b.position));
+        // The boss fight starts over, unless it is already won
+        if !self.monkey.dead() {
+            self.monkey.reset(self.clock.now());
         }
     }
 
-    pub fn update(&mut self, elapsed: f32, keys: &HashSet<Keycode>, sounds: &mut Vec<SoundEffect>) {
-        if !self.started || self.player.dead {
+    /// Replaces the random number generator so runs can be reproduced
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn update(
+        &mut self,
+        elapsed: f32,
+        actions: &HashSet<Action>,
+        sounds: &mut Vec<SoundEffect>,
+    ) {
+        self.store_positions();
+        if !self.started {
+            return;
+        }
+        if self.player.dead {
+            // Deaths play out before the player is back or the game is over
+            if self.player.dying() {
+                let elapsed = self.clock.advance(elapsed);
+                self.player.update_death(elapsed, self.gravity());
+            } else if self.player.lives > 0 {
+                self.respawn();
+            }
+            return;
+        }
+        let elapsed = self.clock.advance(elapsed);
+        if elapsed <= 0.0 {
             return;
         }
 
-        self.player.update(keys, elapsed, &self.tiles, sounds);
+        let gravity = self.gravity();
+        for p in &mut self.platforms {
+            p.update(elapsed);
+        }
+        // Whatever stands on a platform moves along with it
+        for p in &self.platforms {
+            if p.carries(self.player.position, self.player.sides) {
+                self.player.carry(p.displacement, &self.tiles);
+            }
+            for e in self.enemies.iter_mut().filter(|e| p.carries(e.position, e.sides)) {
+                e.position += p.displacement;
+            }
+        }
 
-        self.monkey.udpate(elapsed, self.player.position, &self.tiles, sounds);
+        self.player.update(
+            actions,
+            elapsed,
+            gravity,
+            &self.clock,
+            &self.tiles,
+            &self.platforms,
+            sounds,
+        );
+
+        self.monkey.udpate(
+            elapsed,
+            gravity,
+            &self.clock,
+            self.player.position,
+            &self.tiles,
+            &mut self.rng,
+            sounds,
+        );
 
         if self.player.position.x > self.trap.x {
             self.trapped = true;
         }
 
         for e in &mut self.enemies {
-            e.update(elapsed, &self.tiles);
+            e.update(elapsed, &self.clock, &self.tiles, &self.platforms);
         }
 
         // Player dies by falling out of level bounds
         if self.player.position.y < self.min_bounds().y - self.player.sides.y * 2.0 {
             sounds.push(SoundEffect::Fall);
-            self.player.die();
+            self.player.die(DeathCause::Fall);
+        }
+
+        // The clock keeps going after the monkey is beaten, without running out anymore
+        if self.final_time.is_none() && self.time_left() == Some(Duration::ZERO) {
+            sounds.push(SoundEffect::Dead);
+            self.player.die(DeathCause::Timeout);
         }
 
         // Resolve Collisions
@@ -201,12 +572,14 @@ impl Level {
                 self.monkey.position,
                 self.monkey.hitbox(),
             ) {
-                self.player.die();
-                sounds.push(SoundEffect::Dead);
+                self.player.hurt(DeathCause::Monkey, self.monkey.position, sounds);
             }
             if self.monkey.dead() {
                 self.score += 500;
-                self.final_time = Some(self.timer.elapsed());
+                self.final_time = Some(self.clock.now());
+                if self.header.par_time.is_some_and(|par| self.clock.now() <= par) {
+                    self.score += Level::PAR_BONUS;
+                }
                 println!("Score: {}, Time: {:?}", self.score, self.final_time);
             }
         }
@@ -218,8 +591,7 @@ impl Level {
                 b.position,
                 b.sides,
             ) {
-                self.player.die();
-                sounds.push(SoundEffect::Dead);
+                self.player.hurt(DeathCause::Banana, b.position, sounds);
             }
         }
 
@@ -234,14 +606,43 @@ impl Level {
                 e.position,
                 e.hitbox(),
             ) {
-                self.player.die();
-                sounds.push(SoundEffect::Dead);
+                self.player.hurt(DeathCause::Enemy, e.position, sounds);
             }
             if e.dead() {
                 self.score += 100;
             }
         }
 
+        if !self.player.dead {
+            let hazard = self.hazards.iter().find(|h| {
+                physics::collides(
+                    self.player.hitbox_position(),
+                    self.player.hitbox(),
+                    h.position,
+                    h.sides,
+                )
+            });
+            if let Some(h) = hazard {
+                self.player.die(h.death_cause());
+                sounds.push(h.sound());
+            }
+        }
+
+        for c in self.checkpoints.iter_mut().filter(|c| !c.reached) {
+            if !self.player.dead
+                && physics::collides(
+                    self.player.hitbox_position(),
+                    self.player.hitbox(),
+                    c.position,
+                    c.sides,
+                )
+            {
+                c.reached = true;
+                self.player.spawn = c.respawn;
+                sounds.push(SoundEffect::Click);
+            }
+        }
+
         let min_bounds = self.min_bounds();
         self.monkey.bananas.retain(|b| b.position.y > min_bounds.y);
     }
@@ -250,85 +651,622 @@ impl Level {
         position + (Vec2::Y * (y_side - Tile::SIDE) / 2.0)
     }
 
-    pub fn from_file(filename: &str) -> io::Result<Level> {
-        let level_str = fs::read_to_string(filename)?;
+    pub fn from_file(filename: &str) -> Result<Level, LevelError> {
+        if tiled::is_tiled(filename) {
+            let map = tiled::from_file(filename)?;
+            Level::check_cells(&map.cells, 0)?;
+            Level::check_platforms(&map.header, map.width, map.height)?;
+            return Ok(Level::from_cells(map.header, map.width, map.height, map.cells));
+        }
+        Level::parse(&fs::read_to_string(filename)?)
+    }
 
-        let mut level = Level::new();
+    /// Builds a level from the contents of a text level file
+    pub fn parse(level_str: &str) -> Result<Level, LevelError> {
+        let (header, mut grid, grid_start) = LevelHeader::split(level_str);
+        let header = LevelHeader::parse(&header)?;
+
+        // Trailing empty lines are not part of the grid
+        while grid.last().is_some_and(|line| line.trim().is_empty()) {
+            grid.pop();
+        }
+
+        let width = grid.first().map_or(0, |line| line.chars().count());
+        for (y, line) in grid.iter().enumerate() {
+            let length = line.chars().count();
+            if length != width {
+                return Err(LevelError::RaggedRow {
+                    line: grid_start + y + 1,
+                    length,
+                    expected: width,
+                });
+            }
+        }
+
+        let mut cells = Vec::new();
+        for (y, line) in grid.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let glyph = header.glyph(c).ok_or(LevelError::UnknownGlyph {
+                    line: grid_start + y + 1,
+                    column: x + 1,
+                    glyph: c,
+                })?;
+                cells.push((x, y, glyph));
+            }
+        }
 
-        let level_coords: Vec<_> = level_str
-            .lines()
-            .enumerate()
-            .flat_map(|(y, line)| line.char_indices().map(move |(x, c)| (x, y, c)))
-            .collect();
+        Level::check_cells(&cells, grid_start)?;
+        Level::check_platforms(&header, width, grid.len())?;
+        Ok(Level::from_cells(header, width, grid.len(), cells))
+    }
+
+    /// Makes sure there is exactly one spawn, boss and trap in the grid. Errors locate
+    /// the cells by line and column, with the grid starting after `first_line`.
+    fn check_cells(cells: &[(usize, usize, Glyph)], first_line: usize) -> Result<(), LevelError> {
+        let mut unique: Vec<(Glyph, (usize, usize))> = Vec::new();
+        for &(x, y, glyph) in cells {
+            if [Glyph::Spawn, Glyph::Boss, Glyph::Trap].contains(&glyph) {
+                let (line, column) = (first_line + y + 1, x + 1);
+                if let Some((_, first)) = unique.iter().find(|(g, _)| *g == glyph) {
+                    return Err(LevelError::Duplicate { glyph, line, column, first: *first });
+                }
+                unique.push((glyph, (line, column)));
+            }
+        }
+        for glyph in [Glyph::Spawn, Glyph::Boss, Glyph::Trap] {
+            if !unique.iter().any(|(g, _)| *g == glyph) {
+                return Err(LevelError::Missing(glyph));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure the moving platforms stay within the grid all along their paths
+    fn check_platforms(
+        header: &LevelHeader,
+        width: usize,
+        height: usize,
+    ) -> Result<(), LevelError> {
+        let waypoints = header.platforms.iter().flat_map(|p| &p.waypoints);
+        match waypoints.copied().find(|&(x, y)| x + MovingPlatform::WIDTH > width || y >= height) {
+            Some((x, y)) => Err(LevelError::PlatformOutside { column: x + 1, row: y + 1 }),
+            None => Ok(()),
+        }
+    }
 
-        for (x, y, _) in &level_coords {
-            level.bounds.x = level.bounds.x.max((*x + 1) as f32);
-            level.bounds.y = level.bounds.y.max((*y + 1) as f32);
+    /// Places the glyphs found at each cell of a grid, with rows counted from the top.
+    /// The cells are not checked, so it also builds incomplete levels like editor previews.
+    pub fn from_cells(
+        header: LevelHeader,
+        width: usize,
+        height: usize,
+        cells: Vec<(usize, usize, Glyph)>,
+    ) -> Level {
+        let mut level = Level::new();
+        if let Some(time) = header.coyote_time {
+            level.player.coyote_time = time.as_secs_f32();
+        }
+        if let Some(time) = header.jump_buffer {
+            level.player.jump_buffer = time.as_secs_f32();
         }
+        level.player.abilities = header.abilities.clone();
+        level.header = header;
+        level.bounds = Vec2::new(width as f32, height as f32);
 
         let tile_offset = Vec2::new(Tile::SIDE / 2.0, -Tile::SIDE / 2.0);
         let offset = level.bounds / 2.0;
 
-        for (x, y, c) in level_coords {
+        for (x, y, glyph) in cells {
             let world_pos = Vec2::new(x as f32 - offset.x, -(y as f32) + offset.y) + tile_offset;
-            match c {
-                '[' => {
+            match glyph {
+                Glyph::TileLeft => {
                     level.tiles.push(Tile {
                         position: world_pos,
                         sides: Vec2::new(Tile::SIDE, Tile::SIDE),
                         sprite: Tile::LEFT,
+                        kind: TileKind::Solid,
                     });
                 }
-                '=' => {
+                Glyph::TileCenter => {
                     level.tiles.push(Tile {
                         position: world_pos,
                         sides: Vec2::new(Tile::SIDE, Tile::SIDE),
                         sprite: Tile::CENTER,
+                        kind: TileKind::Solid,
                     });
                 }
-                ']' => {
+                Glyph::TileRight => {
                     level.tiles.push(Tile {
                         position: world_pos,
                         sides: Vec2::new(Tile::SIDE, Tile::SIDE),
                         sprite: Tile::RIGHT,
+                        kind: TileKind::Solid,
                     });
                 }
-                '#' => {
+                Glyph::TileBottom => {
                     level.tiles.push(Tile {
                         position: world_pos,
                         sides: Vec2::new(Tile::SIDE, Tile::SIDE),
                         sprite: Tile::BOTTOM,
+                        kind: TileKind::Solid,
                     });
                 }
-                'D' => {
+                Glyph::SlopeRight => level.tiles.push(Tile::slope(world_pos, 0.0, 1.0)),
+                Glyph::SlopeLeft => level.tiles.push(Tile::slope(world_pos, 1.0, 0.0)),
+                Glyph::RampRightLow => {
+                    level.tiles.push(Tile::slope(world_pos, 0.0, Tile::RAMP_RISE))
+                }
+                Glyph::RampRightHigh => {
+                    level.tiles.push(Tile::slope(world_pos, Tile::RAMP_RISE, 2.0 * Tile::RAMP_RISE))
+                }
+                Glyph::RampLeftHigh => {
+                    level.tiles.push(Tile::slope(world_pos, 2.0 * Tile::RAMP_RISE, Tile::RAMP_RISE))
+                }
+                Glyph::RampLeftLow => {
+                    level.tiles.push(Tile::slope(world_pos, Tile::RAMP_RISE, 0.0))
+                }
+                Glyph::Platform => {
+                    // Thin slab at the top of its cell
+                    let sides = Vec2::new(Tile::SIDE, Tile::SIDE / 2.0);
+                    level.tiles.push(Tile {
+                        position: world_pos + Vec2::Y * (Tile::SIDE - sides.y) / 2.0,
+                        sides,
+                        sprite: Tile::PLATFORM,
+                        kind: TileKind::OneWay,
+                    });
+                }
+                Glyph::Spikes => level.hazards.push(Hazard::new(world_pos, HazardKind::Spikes)),
+                Glyph::Lava => level.hazards.push(Hazard::new(world_pos, HazardKind::Lava)),
+                Glyph::Decor => {
                     let sides = Vec2::new(1.5, 1.5);
                     level.decor.push(Tile {
                         position: Level::offset(world_pos, sides.y),
                         sides,
                         sprite: Tile::BOTTOM,
+                        kind: TileKind::Solid,
                     });
                 }
-                '@' => {
+                Glyph::Trap => {
                     level.trap = world_pos;
                 }
-                'E' => {
+                Glyph::Enemy => {
                     let mut e = Enemy::new();
                     e.spawn = Level::offset(world_pos, e.sides.y);
                     e.position = e.spawn;
                     level.enemies.push(e);
                 }
-                'M' => {
+                Glyph::Boss => {
                     level.monkey.spawn = Level::offset(world_pos, level.monkey.sides.y);
                     level.monkey.position = level.monkey.spawn
                 }
-                'S' => {
+                Glyph::Spawn => {
                     level.player.spawn = Level::offset(world_pos, level.player.sides.y);
                     level.player.position = level.player.spawn;
                 }
-                _ => {}
+                Glyph::Checkpoint => {
+                    let sides = Vec2::new(Tile::SIDE, 2.0 * Tile::SIDE);
+                    level.checkpoints.push(Checkpoint {
+                        position: Level::offset(world_pos, sides.y),
+                        sides,
+                        respawn: Level::offset(world_pos, level.player.sides.y),
+                        reached: false,
+                    });
+                }
+                Glyph::Empty => {}
             }
         }
 
-        Ok(level)
+        for path in &level.header.platforms {
+            // Thin slabs at the top of the cells, with waypoints giving their left end
+            let sides = Vec2::new(MovingPlatform::WIDTH as f32 * Tile::SIDE, Tile::SIDE / 2.0);
+            let center = Vec2::new((sides.x - Tile::SIDE) / 2.0, (Tile::SIDE - sides.y) / 2.0);
+            let waypoints = path
+                .waypoints
+                .iter()
+                .map(|&(x, y)| {
+                    Vec2::new(x as f32 - offset.x, -(y as f32) + offset.y) + tile_offset + center
+                })
+                .collect();
+            level.platforms.push(MovingPlatform::new(waypoints, path.looping, sides));
+        }
+
+        level.store_positions();
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: &str = "\
+..........
+.S...E..@M
+[========]
+";
+
+    #[test]
+    fn parses_header_and_grid() {
+        let level = Level::parse(&format!("name: Test\ntime_limit: 1:30\n---\n{}", GRID)).unwrap();
+        assert_eq!(level.header.name.as_deref(), Some("Test"));
+        assert_eq!(level.header.time_limit, Some(Duration::from_secs(90)));
+        assert_eq!(level.max_bounds(), Vec2::new(5.0, 1.5));
+        assert_eq!(level.enemies.len(), 1);
+        assert_eq!(level.player.spawn.x, -3.5);
+    }
+
+    #[test]
+    fn grid_without_header() {
+        let level = Level::parse(&format!("{}\n\n", GRID)).unwrap();
+        assert_eq!(level.max_bounds(), Vec2::new(5.0, 1.5));
+    }
+
+    #[test]
+    fn ragged_rows_are_located() {
+        let error = Level::parse("name: Test\n---\n..........\n.S...E..@M\n[=======]\n");
+        assert!(matches!(error, Err(LevelError::RaggedRow { line: 5, length: 9, expected: 10 })));
+    }
+
+    #[test]
+    fn unknown_glyphs_are_located() {
+        let error = Level::parse("..........\n.S..?E..@M\n[========]\n");
+        assert!(matches!(error, Err(LevelError::UnknownGlyph { line: 2, column: 5, glyph: '?' })));
+    }
+
+    #[test]
+    fn legend_adds_glyphs() {
+        let level = Level::parse("legend: x = enemy\n---\n..........\n.S.x.E..@M\n[========]\n");
+        assert_eq!(level.unwrap().enemies.len(), 2);
+    }
+
+    #[test]
+    fn missing_and_duplicate_entities() {
+        let error = Level::parse("..........\n.....E..@M\n[========]\n");
+        assert!(matches!(error, Err(LevelError::Missing(Glyph::Spawn))));
+
+        let error = Level::parse("---\n..S.......\n.S...E..@M\n[========]\n");
+        assert!(matches!(
+            error,
+            Err(LevelError::Duplicate { glyph: Glyph::Spawn, line: 3, column: 2, first: (2, 3) })
+        ));
+    }
+
+    fn tile(x: f32, y: f32) -> Tile {
+        Tile {
+            position: Vec2::new(x, y),
+            sides: Vec2::ONE,
+            sprite: Tile::CENTER,
+            kind: TileKind::Solid,
+        }
+    }
+
+    #[test]
+    fn grid_finds_nearby_tiles() {
+        let mut grid = TileGrid::default();
+        for x in -5..5 {
+            grid.push(tile(x as f32 + 0.5, -0.5));
+        }
+        grid.push(tile(-20.5, 10.5));
+
+        let mut near: Vec<f32> =
+            grid.near(Vec2::new(0.0, 0.5), Vec2::ONE).map(|t| t.position.x).collect();
+        near.sort_by(f32::total_cmp);
+        assert_eq!(near, vec![-0.5, 0.5, 1.5]);
+        // Bigger rectangles reach more tiles, negative cells included
+        assert_eq!(grid.near(Vec2::new(-20.0, 10.0), Vec2::splat(2.0)).count(), 1);
+        assert_eq!(grid.near(Vec2::new(0.0, 0.5), Vec2::new(6.0, 1.0)).count(), 8);
+        assert_eq!(grid.near(Vec2::new(0.0, 5.0), Vec2::ONE).count(), 0);
+        assert_eq!(grid.iter().count(), 11);
+    }
+
+    #[test]
+    fn ground_is_the_highest_top_in_reach() {
+        let mut grid = TileGrid::default();
+        grid.push(tile(0.5, -0.5));
+        grid.push(Tile::slope(Vec2::new(1.5, 0.5), 0.0, 1.0));
+
+        let top = |x: f32, y: f32| grid.ground(Vec2::new(x, y), Vec2::ONE, 0.5, 0.5, |_| true);
+        assert_eq!(top(0.5, 0.6), Some((0.0, TileKind::Solid)));
+        // Half over the slope, standing where it is highest under the rectangle
+        assert_eq!(top(1.0, 1.0).map(|(top, _)| top), Some(0.5));
+        assert_eq!(top(0.5, 3.0), None);
+    }
+
+    #[test]
+    fn platforms_stay_in_the_grid() {
+        let level = Level::parse(&format!("moving_platform: 1,1 8,2\n---\n{}", GRID));
+        assert_eq!(level.unwrap().platforms.len(), 1);
+
+        let error = Level::parse(&format!("moving_platform: 1,1 9,2\n---\n{}", GRID));
+        assert!(matches!(error, Err(LevelError::PlatformOutside { column: 9, row: 2 })));
+        let error = Level::parse(&format!("moving_platform: 1,4 1,1\n---\n{}", GRID));
+        assert!(matches!(error, Err(LevelError::PlatformOutside { column: 1, row: 4 })));
+    }
+
+    // Runs the level for a number of updates at 60 per second with the actions held
+    fn run(level: &mut Level, updates: usize, actions: &[Action]) {
+        let actions = actions.iter().copied().collect();
+        for _ in 0..updates {
+            level.update(1.0 / 60.0, &actions, &mut Vec::new());
+        }
+    }
+
+    #[test]
+    fn slope_sides_are_walls() {
+        let mut level = Level::parse("..........\n......S@.M\n..../.....\n[========]\n").unwrap();
+        level.start();
+        run(&mut level, 120, &[Action::MoveLeft]);
+        // Stopped by the full height side of the slope instead of walking into it
+        let slope_side = 0.0;
+        assert!(level.player.position.x - level.player.sides.x / 2.0 >= slope_side - 0.01);
+
+        // Walking the other way goes up the slope from its low side
+        let mut level = Level::parse("..........\n.S.....@.M\n..../.....\n[========]\n").unwrap();
+        level.start();
+        run(&mut level, 90, &[Action::MoveRight]);
+        assert!(level.player.position.x > 0.5, "{:?}", level.player.position);
+    }
+
+    #[test]
+    fn ramps_rise_at_22_5_degrees() {
+        let level = Level::parse("..........\n.S.rRLl.@M\n[========]\n").unwrap();
+        let ramps: Vec<&Tile> = level.tiles.iter().filter(|t| t.sloped()).collect();
+        assert_eq!(ramps.len(), 4);
+        for ramp in ramps {
+            let x = ramp.position.x;
+            let rise = (ramp.top(x + 0.5) - ramp.top(x - 0.5)).abs();
+            assert!((rise - 22.5f32.to_radians().tan()).abs() < 0.001, "{}", rise);
+        }
+    }
+
+    #[test]
+    fn slopes_block_jumps_from_below() {
+        let mut level = Level::parse("..../.....\n..........\n....S..@.M\n[========]\n").unwrap();
+        level.start();
+        let mut highest = f32::MIN;
+        for _ in 0..60 {
+            run(&mut level, 1, &[Action::Jump]);
+            highest = highest.max(level.player.position.y + level.player.sides.y / 2.0);
+        }
+        assert!(highest <= 1.0 + 0.01, "head went up to {}", highest);
+    }
+
+    // Level made of the rows given, widened so the monkey at the end of the second row
+    // is too far away to throw bananas at the player
+    fn away_from_the_monkey(rows: &[&str]) -> Level {
+        let width = 60;
+        let mut grid = String::new();
+        for (i, row) in rows.iter().enumerate() {
+            let fill = if i + 1 == rows.len() { "=" } else { "." };
+            let end = if i == 1 { "@M" } else { fill };
+            grid += row;
+            grid += &fill.repeat(width - row.len() - end.len());
+            grid += end;
+            grid += "\n";
+        }
+        Level::parse(&grid).unwrap()
+    }
+
+    #[test]
+    fn one_way_platforms_are_dropped_through() {
+        let mut level =
+            away_from_the_monkey(&["..........", ".S", ".___", "..", "..", "[========"]);
+        level.start();
+        run(&mut level, 60, &[]);
+        let feet = |level: &Level| level.player.position.y - level.player.sides.y / 2.0;
+        let platform = feet(&level);
+        assert_eq!(level.player.velocity.y, 0.0);
+
+        // Crouching alone keeps the player on it
+        run(&mut level, 30, &[Action::Crouch]);
+        assert_eq!(feet(&level), platform);
+        // Jumping while crouched drops down to the floor below
+        run(&mut level, 1, &[Action::Crouch, Action::Jump]);
+        run(&mut level, 60, &[Action::Crouch]);
+        assert_eq!(feet(&level), platform - 3.0);
+
+        // And it is jumped up through from below, landing on top of it
+        run(&mut level, 30, &[Action::Jump]);
+        run(&mut level, 90, &[]);
+        assert_eq!(feet(&level), platform);
+    }
+
+    #[test]
+    fn one_way_platforms_are_floor_for_enemies() {
+        let mut level = away_from_the_monkey(&["..........", ".S..E", "..____", "..", "..", "["]);
+        let start = level.enemies[0].position;
+        level.start();
+        for _ in 0..300 {
+            run(&mut level, 1, &[]);
+            let enemy = &level.enemies[0];
+            assert!((enemy.position.y - start.y).abs() < 0.01, "{:?}", enemy.position);
+        }
+    }
+
+    #[test]
+    fn hazards_kill_with_their_own_cause() {
+        let cases = [
+            ('^', DeathCause::Spikes, SoundEffect::Spikes),
+            ('~', DeathCause::Lava, SoundEffect::Lava),
+        ];
+        for (glyph, cause, sound) in cases.iter() {
+            let mut level = away_from_the_monkey(&["..", &format!(".S..{}", glyph), "["]);
+            level.start();
+            let mut sounds = Vec::new();
+            let actions = [Action::MoveRight].iter().copied().collect();
+            while !level.player.dead && level.player.position.x < 0.0 {
+                level.update(1.0 / 60.0, &actions, &mut sounds);
+            }
+            assert_eq!(level.player.death_cause, Some(*cause));
+            assert_eq!(level.player.lives, Player::LIVES - 1);
+            assert!(sounds.contains(sound));
+        }
+    }
+
+    #[test]
+    fn hits_take_health_and_throw_the_player_back() {
+        let mut level = away_from_the_monkey(&["..", &format!("{}S...E", ".".repeat(20)), "["]);
+        level.start();
+        while level.player.health == Player::MAX_HEALTH {
+            run(&mut level, 1, &[]);
+        }
+        assert_eq!(level.player.health, Player::MAX_HEALTH - 1);
+        assert!(level.player.invulnerable() > 0.0);
+        // Away from the enemy coming from the right
+        assert!(level.player.velocity.x < 0.0 && level.player.velocity.y > 0.0);
+
+        // Nothing hurts while invulnerable
+        let mut sounds = Vec::new();
+        let enemy = level.enemies.pop().unwrap();
+        level.player.hurt(DeathCause::Enemy, enemy.position, &mut sounds);
+        assert_eq!(level.player.health, Player::MAX_HEALTH - 1);
+        assert!(sounds.is_empty());
+
+        // Losing the last point of health costs a life
+        for _ in 0..2 {
+            run(&mut level, 150, &[]);
+            level.player.hurt(DeathCause::Enemy, enemy.position, &mut sounds);
+        }
+        assert!(level.player.dead);
+        assert_eq!(level.player.death_cause, Some(DeathCause::Enemy));
+        assert_eq!(level.player.lives, Player::LIVES - 1);
+    }
+
+    #[test]
+    fn the_game_is_over_when_the_last_life_is_lost() {
+        let mut level = Level::parse(GRID).unwrap();
+        level.start();
+        for lives in (1..Player::LIVES).rev() {
+            level.player.die(DeathCause::Fall);
+            assert_eq!(level.player.lives, lives);
+            run(&mut level, 120, &[]);
+            assert!(!level.player.dead && !level.player.game_over());
+            assert_eq!(level.player.health, Player::MAX_HEALTH);
+        }
+        level.player.die(DeathCause::Fall);
+        run(&mut level, 120, &[]);
+        assert!(level.player.dead && level.player.game_over());
+    }
+
+    // Walks off a ledge then presses jump after falling for a number of updates, returning
+    // whether the player went up again
+    fn jump_after_ledge(coyote_time: f32, falling: usize) -> bool {
+        let mut level = away_from_the_monkey(&["..", ".S", "[===", "..", "..", "["]);
+        level.player.coyote_time = coyote_time;
+        level.start();
+        run(&mut level, 30, &[]);
+        while level.player.velocity.y >= 0.0 {
+            run(&mut level, 1, &[Action::MoveRight]);
+        }
+        run(&mut level, falling, &[Action::MoveRight]);
+        run(&mut level, 1, &[Action::MoveRight, Action::Jump]);
+        level.player.velocity.y > 0.0
+    }
+
+    #[test]
+    fn jumps_are_allowed_just_after_leaving_a_ledge() {
+        assert!(jump_after_ledge(0.1, 3));
+        assert!(!jump_after_ledge(0.1, 10));
+        // Turned off, only the ground allows jumping
+        assert!(!jump_after_ledge(0.0, 0));
+    }
+
+    #[test]
+    fn jumps_pressed_just_before_landing_happen_on_landing() {
+        let level = || {
+            let mut level = away_from_the_monkey(&[".S", "..", "..", "..", "..", "["]);
+            level.start();
+            level
+        };
+        let mut falling = level();
+        let mut landing = 0;
+        while falling.player.velocity.y != 0.0 || landing == 0 {
+            run(&mut falling, 1, &[]);
+            landing += 1;
+        }
+
+        // Jump pressed for a single update some time before landing
+        let jumped = |early: usize| {
+            let mut level = level();
+            run(&mut level, landing - early, &[]);
+            run(&mut level, 1, &[Action::Jump]);
+            run(&mut level, early, &[]);
+            level.player.velocity.y > 0.0
+        };
+        assert!(jumped(3));
+        assert!(!jumped(20));
+    }
+
+    #[test]
+    fn respawning_resets_the_monkey() {
+        let mut level = Level::parse(GRID).unwrap();
+        level.start();
+        run(&mut level, 10, &[]);
+        level.monkey.damage(1);
+        level.monkey.position.x -= 2.0;
+        level.monkey.enranged = true;
+        level.player.die(DeathCause::Fall);
+        run(&mut level, 120, &[]);
+
+        assert!(!level.player.dead);
+        assert_eq!(level.monkey.position, level.monkey.spawn);
+        assert!(!level.monkey.enranged);
+        // Takes the three hits again, calming down between them
+        for _ in 0..2 {
+            assert!(level.monkey.damage(1) && !level.monkey.dead());
+            level.monkey.enranged = false;
+        }
+        assert!(level.monkey.damage(1) && level.monkey.dead());
+    }
+
+    #[test]
+    fn respawning_starts_a_fresh_life() {
+        let mut level = Level::parse("..........\n.S......@M\n[========]\n").unwrap();
+        level.start();
+        run(&mut level, 30, &[]);
+        run(&mut level, 1, &[Action::Dash, Action::Sprint]);
+        assert!(level.player.dashing());
+        level.player.die(DeathCause::Fall);
+        while level.player.dead {
+            run(&mut level, 1, &[Action::Dash]);
+        }
+        assert!(level.player.trail.is_empty());
+        // Still holding the dash from the previous life starts a new one
+        run(&mut level, 1, &[Action::Dash]);
+        assert!(level.player.dashing());
+    }
+
+    #[test]
+    fn jumping_ends_the_dash() {
+        let grid = "....................\n.S...............@.M\n[==================]\n";
+        let mut level = Level::parse(grid).unwrap();
+        level.start();
+        run(&mut level, 30, &[]);
+        let ground = level.player.position.y;
+        run(&mut level, 3, &[Action::Dash]);
+        assert!(level.player.dashing());
+        run(&mut level, 1, &[Action::Dash, Action::Jump]);
+        assert!(!level.player.dashing());
+        // Back to the walking speed instead of the dash one
+        assert_eq!(level.player.velocity.x, 10.0);
+        run(&mut level, 10, &[Action::Jump]);
+        assert!(level.player.position.y > ground + 1.0);
+    }
+
+    #[test]
+    fn opposite_directions_cancel_out() {
+        let mut level = Level::parse("..........\n.S......@M\n[========]\n").unwrap();
+        level.start();
+        run(&mut level, 30, &[]);
+        let start = level.player.position.x;
+        run(&mut level, 30, &[Action::MoveLeft, Action::MoveRight, Action::Sprint]);
+        assert_eq!(level.player.velocity.x, 0.0);
+        assert_eq!(level.player.position.x, start);
+    }
+
+    #[test]
+    fn header_errors_come_first() {
+        let error = Level::parse(&format!("gravity: heavy\n---\n{}", GRID));
+        assert!(matches!(error, Err(LevelError::Header { line: 1, .. })));
     }
 }
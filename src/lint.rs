@@ -0,0 +1,130 @@
+use glam::Vec2;
+use std::collections::HashSet;
+use std::fs;
+use std::iter;
+
+use crate::header::{LevelHeader, PlatformPath};
+use crate::level::Level;
+use crate::physics;
+use crate::platform::MovingPlatform;
+use crate::player::Player;
+
+// Grid cell of the bottom of an object, as (column, row) from the top left corner
+fn cell(level: &Level, position: Vec2, sides: Vec2) -> (i32, i32) {
+    let max = level.max_bounds();
+    let bottom = position.y - sides.y / 2.0 + 0.5;
+    ((position.x + max.x).floor() as i32, (max.y - bottom).floor() as i32)
+}
+
+// Cells a moving platform passes through, all of them floors the player can land on
+fn platform_cells(path: &PlatformPath) -> Vec<(i32, i32)> {
+    let mut points: Vec<Vec2> =
+        path.waypoints.iter().map(|&(x, y)| Vec2::new(x as f32, y as f32)).collect();
+    if path.looping {
+        points.push(points[0]);
+    }
+    let mut cells = Vec::new();
+    for segment in points.windows(2) {
+        let steps = (segment[1] - segment[0]).abs().max_element().ceil().max(1.0);
+        for i in 0..=steps as i32 {
+            let point = segment[0].lerp(segment[1], i as f32 / steps).round();
+            for dx in 0..MovingPlatform::WIDTH as i32 {
+                cells.push((point.x as i32 + dx, point.y as i32));
+            }
+        }
+    }
+    cells
+}
+
+// Platform edges with a gap after them wider than a sprinting jump and a dash can clear
+fn jump_gaps(level: &Level) -> Vec<((i32, i32), String)> {
+    let reach = Player::jump_reach(level.gravity(), &level.header.abilities);
+    let tiles: HashSet<(i32, i32)> =
+        level.tiles.iter().map(|t| cell(level, t.position, t.sides)).collect();
+    let mut floors: Vec<(i32, i32)> =
+        tiles.iter().copied().filter(|&(x, y)| !tiles.contains(&(x, y - 1))).collect();
+    floors.extend(level.header.platforms.iter().flat_map(platform_cells));
+
+    let mut edges: Vec<(i32, i32)> = floors
+        .iter()
+        .copied()
+        .filter(|&(x, y)| !tiles.contains(&(x + 1, y)) && !tiles.contains(&(x + 1, y - 1)))
+        .collect();
+    edges.sort_unstable();
+
+    let mut gaps = Vec::new();
+    for (x, y) in edges {
+        // Nearest floor to the right that is not too high to land on
+        let landing = floors
+            .iter()
+            .filter(|&&(fx, fy)| fx > x && (y - fy) as f32 <= reach.y)
+            .map(|&(fx, _)| fx - x - 1)
+            .min();
+        if let Some(gap) = landing.filter(|&gap| gap as f32 > reach.x) {
+            let message =
+                format!("gap of {} tiles is wider than a jump can clear ({:.1})", gap, reach.x);
+            gaps.push(((x, y), message));
+        }
+    }
+    gaps
+}
+
+fn problems(level: &Level) -> Vec<((i32, i32), String)> {
+    let mut problems = Vec::new();
+
+    // The player starts at the spawn and comes back at the checkpoints
+    let player = &level.player;
+    let starts = iter::once((player.spawn, "player spawn"))
+        .chain(level.checkpoints.iter().map(|c| (c.respawn, "checkpoint")));
+    for (start, name) in starts {
+        let start_cell = cell(level, start, player.sides);
+        if level.tiles.iter().any(|t| physics::collides(start, player.sides, t.position, t.sides)) {
+            problems.push((start_cell, format!("{} intersects a tile", name)));
+        }
+        let below = |position: Vec2, sides: Vec2| {
+            (position.x - start.x).abs() < (player.sides.x + sides.x) / 2.0 && position.y < start.y
+        };
+        let floor_below = level.tiles.iter().any(|t| below(t.position, t.sides))
+            || level.platforms.iter().any(|p| below(p.position, p.sides));
+        if !floor_below {
+            let message = format!("{} is unreachable, there is no floor below it", name);
+            problems.push((start_cell, message));
+        }
+    }
+
+    for e in &level.enemies {
+        // Same check Enemy::update uses to find the ground ahead
+        if !e.floor_at(e.spawn, &level.tiles, &level.platforms) {
+            let message = String::from("enemy is in mid-air and will turn around every frame");
+            problems.push((cell(level, e.spawn, e.sides), message));
+        }
+    }
+
+    if level.trap().x > level.monkey.spawn.x {
+        let message = String::from("trap is placed after the boss");
+        problems.push((cell(level, level.trap(), Vec2::ONE), message));
+    }
+
+    problems.extend(jump_gaps(level));
+    problems
+}
+
+/// Loads a level without opening a window and reports the problems found in it
+pub fn check(level_file: &str) -> Result<(), String> {
+    let level = Level::from_file(level_file).map_err(|e| format!("{}: {}", level_file, e))?;
+    let level_str = fs::read_to_string(level_file).map_err(|e| e.to_string())?;
+    let (_, _, grid_start) = LevelHeader::split(&level_str);
+
+    let problems = problems(&level);
+    for ((x, y), message) in &problems {
+        let (line, column) = (grid_start as i32 + y + 1, x + 1);
+        println!("{}: line {}, column {}: {}", level_file, line, column, message);
+    }
+    match problems.len() {
+        0 => {
+            println!("{}: no problems found", level_file);
+            Ok(())
+        }
+        n => Err(format!("{}: {} problem(s) found", level_file, n)),
+    }
+}
@@ -1,32 +1,144 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod campaign;
+mod clock;
+mod editor;
+mod header;
+mod headless;
+mod input;
 mod level;
+mod lint;
 mod monkey;
 mod physics;
+mod platform;
 mod player;
 mod render;
+mod replay;
 mod sound;
+mod state;
+mod tiled;
+mod watcher;
 
+use campaign::Campaign;
+use editor::Editor;
+use input::{Controllers, KeyBindings};
 use level::Level;
 use render::Camera;
 use render::{TextRenderer, TextureManager};
-use sound::SoundEffect;
+use replay::Replay;
+use sound::{Sound, SoundEffect};
+use state::{Command, GameState, Settings};
+use watcher::Watcher;
 
 use glam::Vec2;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::ttf;
+use std::collections::HashSet;
 use std::env;
+use std::path::Path;
 use std::time::Instant;
 
 const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
 
+// Longest frame the simulation catches up with, so a stall doesn't snowball into
+// ever more steps per frame
+const MAX_FRAME_TIME: f32 = 0.25;
+
+const CAMPAIGN_FILE: &str = "assets/campaign.txt";
+
+const ASSETS_DIR: &str = "assets";
+
+// Value following a command line option, if any
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    args.get(i + 1).filter(|arg| !arg.starts_with("--")).map(String::as_str)
+}
+
+fn load_level(
+    replay: Option<&Replay>,
+    campaign: &Campaign,
+    time_scale: f32,
+) -> Result<Level, String> {
+    match replay {
+        Some(replay) => {
+            let mut level =
+                Level::from_file(&replay.level).map_err(|e| format!("{}: {}", replay.level, e))?;
+            level.reseed(replay.seed);
+            level.start();
+            Ok(level)
+        }
+        None => {
+            let mut level = campaign.load_level()?;
+            level.clock.set_scale(time_scale);
+            Ok(level)
+        }
+    }
+}
+
+// Switches to the music and backgrounds requested by the level header
+fn load_level_assets(
+    level: &Level,
+    settings: &Settings,
+    tx_manager: &mut TextureManager,
+    sound_module: &mut Sound,
+) -> Result<(), String> {
+    // Missing files of a level fall back to the default background and music
+    if let Err(e) = tx_manager.set_backgrounds(&level.header.background) {
+        println!("Error loading level backgrounds: {}", e);
+        tx_manager.set_backgrounds(&[])?;
+    }
+    if let Err(e) = sound_module.play_track(level.header.music.as_deref()) {
+        println!("Error loading level music: {}", e);
+        sound_module.play_track(None)?;
+    }
+    sound_module.set_music(settings.music);
+    Ok(())
+}
+
 fn main() -> Result<(), String> {
-    let fixed = env::args().find(|arg| arg == "--fixed").is_some();
+    let args: Vec<String> = env::args().collect();
+
+    let record_file = arg_value(&args, "--record");
+    let replay = arg_value(&args, "--replay").map(Replay::from_file).transpose()?;
+
+    if let Some(level_file) = arg_value(&args, "--check-level") {
+        return lint::check(level_file);
+    }
+
+    if args.iter().any(|arg| arg == "--headless") {
+        return match &replay {
+            Some(replay) => headless::replay(replay),
+            None => {
+                // The campaign is only needed for its first level
+                let level_file = match arg_value(&args, "--level") {
+                    Some(level_file) => level_file.to_string(),
+                    None => Campaign::from_file(CAMPAIGN_FILE)?.level_file().to_string(),
+                };
+                headless::run(&level_file, arg_value(&args, "--headless"))
+            }
+        };
+    }
+
+    let mut campaign = Campaign::from_file(CAMPAIGN_FILE)?;
+
+    let fixed = args.iter().any(|arg| arg == "--fixed");
     if fixed {
         println!("Using fixed timestep: {}", FIXED_TIMESTEP);
     }
 
+    let mut watcher = if args.iter().any(|arg| arg == "--watch") {
+        println!("Watching {} for changes", ASSETS_DIR);
+        Some(Watcher::new(ASSETS_DIR))
+    } else {
+        None
+    };
+
+    let time_scale = match arg_value(&args, "--speed") {
+        Some(speed) => speed.parse::<f32>().map_err(|e| format!("Invalid speed: {}", e))?,
+        None => 1.0,
+    };
+
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
@@ -43,7 +155,7 @@ fn main() -> Result<(), String> {
         .expect("could not build canvas from window, quiting");
 
     let texture_creator = canvas.texture_creator();
-    let tx_manager = TextureManager::load(&texture_creator)?;
+    let mut tx_manager = TextureManager::load(&texture_creator)?;
 
     // Font Subsystem
     let ttf_context = ttf::init().map_err(|e| e.to_string())?;
@@ -56,59 +168,232 @@ fn main() -> Result<(), String> {
 
     // Audio Subsystem
     let _audio = sdl_context.audio()?;
-    let sound_module = sound::Sound::load()?;
+    let mut sound_module = Sound::load()?;
     sound_module.play_music()?;
 
-    let mut level = Level::from_file("assets/level.txt") //
-        .expect("Error loading level from file");
+    // Input
+    let bindings = KeyBindings::from_file("assets/controls.txt")?;
+    let mut controllers = Controllers::new(sdl_context.game_controller()?);
+
+    let mut level = load_level(replay.as_ref(), &campaign, time_scale)?;
+    let mut recording = Some(Replay::new(level.seed(), campaign.level_file()));
+    let mut replay_frame = 0;
+
+    let mut editor: Option<Editor> = None;
+    let mut settings = Settings::new();
+    let mut state = if replay.is_some() { GameState::Playing } else { GameState::Title };
+    load_level_assets(&level, &settings, &mut tx_manager, &mut sound_module)?;
 
     let mut timer = Instant::now();
+    let mut accumulator = 0.0;
     let mut event_pump = sdl_context.event_pump()?;
     'running: loop {
+        let mut reload = false;
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running;
+            controllers.handle_event(&event);
+            if let Event::Quit { .. } = event {
+                break 'running;
+            }
+            if let (GameState::Editing, Some(editor)) = (state, &mut editor) {
+                editor.handle_event(&event, &camera);
+            }
+
+            let (pressed, released) = match event {
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                    (bindings.action(key), None)
+                }
+                Event::KeyUp { keycode: Some(key), .. } => (None, bindings.action(key)),
+                Event::ControllerButtonDown { button, .. } => (Controllers::action(button), None),
+                Event::ControllerButtonUp { button, .. } => (None, Controllers::action(button)),
+                _ => (None, None),
+            };
+
+            let (next_state, command) = match (pressed, released) {
+                (Some(action), _) => state.pressed(action, &campaign, &level),
+                (_, Some(action)) => state.released(action, &mut settings, &campaign),
+                _ => (state, None),
+            };
+            let previous = state;
+            state = next_state;
+
+            match command {
+                Some(Command::Start) => level.start(),
+                Some(Command::Restart) => {
+                    campaign.restart(&level);
+                    reload = true;
                 }
-                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
-                    level = Level::from_file("assets/level.txt") //
-                        .expect("Error loading level from file");
-                    level.start();
+                Some(Command::NextLevel) => {
+                    campaign.advance(&level);
+                    reload = true;
                 }
-                Event::KeyUp { keycode: Some(Keycode::Space), .. } if !level.started() => {
-                    level.start();
+                Some(Command::SelectLevel(i)) => {
+                    campaign.select(i);
+                    reload = true;
                 }
-                _ => {}
+                Some(Command::Quit) => break 'running,
+                Some(Command::ApplySettings) => sound_module.set_music(settings.music),
+                // Edits are kept while the editor stays on the same level
+                Some(Command::Edit)
+                    if editor.as_ref().map(Editor::file) != Some(campaign.level_file()) =>
+                {
+                    match Editor::from_file(campaign.level_file()) {
+                        Ok(e) => editor = Some(e),
+                        Err(e) => {
+                            println!("Error opening editor: {}", e);
+                            state = previous;
+                        }
+                    }
+                }
+                Some(Command::Edit) => {}
+                Some(Command::Playtest) => reload = true,
+                None => {}
             }
         }
-        let elapsed = if fixed {
+
+        // Hot reload of the files changed on disk, art changes keep the level running
+        let level_file = replay.as_ref().map_or(campaign.level_file(), |r| r.level.as_str());
+        for file in watcher.as_mut().map(Watcher::poll).unwrap_or_default() {
+            println!("Reloading {}", file.display());
+            let result = match file.extension().and_then(|e| e.to_str()) {
+                Some("png") => tx_manager.reload(),
+                Some("wav") | Some("ogg") => {
+                    sound_module.reload(&file).map(|_| sound_module.set_music(settings.music))
+                }
+                // Saves from the editor are already in the level being edited
+                _ if file == Path::new(level_file) && state != GameState::Editing => {
+                    Level::from_file(level_file)
+                        .map(|_| {
+                            editor = None;
+                            reload = true;
+                        })
+                        .map_err(|e| e.to_string())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                println!("Error reloading {}: {}", file.display(), e);
+            }
+        }
+
+        if reload {
+            let loaded = match &mut editor {
+                // Playtests use the level being edited, even when not saved
+                Some(editor) if editor.file() == campaign.level_file() => match editor.level() {
+                    Ok(mut level) => {
+                        level.score = campaign.score;
+                        level.player.lives = campaign.lives;
+                        level.clock.set_scale(time_scale);
+                        Some((level, editor.modified()))
+                    }
+                    Err(e) => {
+                        editor.message = Some(e);
+                        state = GameState::Editing;
+                        None
+                    }
+                },
+                _ => Some((load_level(replay.as_ref(), &campaign, time_scale)?, false)),
+            };
+            if let Some((loaded, unsaved)) = loaded {
+                level = loaded;
+                level.start();
+                // Unsaved edits aren't in the level file a replay would point to
+                recording =
+                    Some(Replay::new(level.seed(), campaign.level_file())).filter(|_| !unsaved);
+                replay_frame = 0;
+                load_level_assets(&level, &settings, &mut tx_manager, &mut sound_module)?;
+            }
+        }
+        // Menus freeze the simulation while rendering continues
+        level.clock.set_paused(!state.simulating());
+
+        // Real time is turned into steps of constant length, the remainder carried over
+        let frame_time = if fixed {
             FIXED_TIMESTEP
         } else {
             let delta = timer.elapsed();
             timer += delta;
-            delta.as_millis() as f32 / 1000.0
+            delta.as_secs_f32().min(MAX_FRAME_TIME)
         };
+        accumulator += frame_time;
+
+        // Map the pressed keys and controller buttons to actions
+        let mut actions = bindings.actions(
+            event_pump.keyboard_state().pressed_scancodes().filter_map(Keycode::from_scancode),
+        );
+        actions.extend(controllers.actions());
 
-        // Create a set of pressed Keys
-        let keys = event_pump
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
+        if let (GameState::Editing, Some(editor)) = (state, &mut editor) {
+            editor.update(&actions, frame_time);
+        }
 
         let mut sounds = Vec::<SoundEffect>::new();
-        level.update(elapsed, &keys, &mut sounds);
+        while accumulator >= FIXED_TIMESTEP {
+            accumulator -= FIXED_TIMESTEP;
 
-        sound_module.play_sounds(sounds);
+            // Replays feed the recorded frames instead, freezing once they run out
+            let (elapsed, actions) = match &replay {
+                Some(replay) if !level.clock.paused() => {
+                    let frame = replay.frames.get(replay_frame).cloned();
+                    replay_frame += 1;
+                    frame.unwrap_or((0.0, HashSet::new()))
+                }
+                _ => (FIXED_TIMESTEP, actions.clone()),
+            };
 
-        if level.trapped {
-            let bottom_right = Vec2::new(level.max_bounds().x, level.min_bounds().y);
-            camera.recenter(bottom_right, level.max_bounds());
-        } else {
-            camera.recenter(level.player.position, level.max_bounds());
+            if let Some(recording) = recording.as_mut() {
+                if level.started() && !level.player.game_over() && !level.clock.paused() {
+                    // Replays store game time so they play back the same at any speed
+                    recording.record(elapsed * level.clock.scale(), &actions);
+                }
+            }
+            level.update(elapsed, &actions, &mut sounds);
+            state = state.update(&level);
+        }
+        let alpha = accumulator / FIXED_TIMESTEP;
+
+        if settings.sounds {
+            sound_module.play_sounds(sounds);
         }
 
-        render::render(&mut canvas, &camera, &level, &tx_manager, &text_renderer)?;
+        // The editor shows the level as it is being painted
+        let shown = match (state, &editor) {
+            (GameState::Editing, Some(editor)) => {
+                camera.recenter(editor.center, editor.preview.max_bounds());
+                &editor.preview
+            }
+            _ if level.trapped => {
+                let bottom_right = Vec2::new(level.max_bounds().x, level.min_bounds().y);
+                camera.recenter(bottom_right, level.max_bounds());
+                &level
+            }
+            _ => {
+                let position = level.player.previous_position.lerp(level.player.position, alpha);
+                camera.recenter(position, level.max_bounds());
+                &level
+            }
+        };
+
+        render::render(
+            &mut canvas,
+            &camera,
+            shown,
+            alpha,
+            &state,
+            &settings,
+            &campaign,
+            editor.as_ref(),
+            &mut tx_manager,
+            &text_renderer,
+        )?;
+    }
+
+    match (record_file, recording) {
+        (Some(filename), Some(recording)) => {
+            recording.save(filename).map_err(|e| e.to_string())?;
+            println!("Replay saved to {}", filename);
+        }
+        (Some(_), None) => println!("Replay not saved, the last run played unsaved edits"),
+        _ => {}
     }
 
     Ok(())
@@ -1,10 +1,12 @@
-use rand::{self, Rng};
-use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::time::Duration;
 use std::vec::Vec;
 
 use glam::Vec2;
 
-use crate::level::Tile;
+use crate::clock::Clock;
+use crate::level::TileGrid;
 use crate::physics;
 use crate::sound::SoundEffect;
 
@@ -12,6 +14,7 @@ use crate::sound::SoundEffect;
 pub struct Monkey {
     pub spawn: Vec2,
     pub position: Vec2,
+    pub previous_position: Vec2,
     pub sides: Vec2,
     pub velocity: Vec2,
     pub bananas: Vec<Banana>,
@@ -19,7 +22,7 @@ pub struct Monkey {
     bananas_thrown: i32,
     bananas_before_rage: i32,
     pub enranged: bool,
-    ai_timer: Instant,
+    ai_timer: Duration,
     rage_velocity: Vec2,
     health: i32,
     pub right: bool,
@@ -36,13 +39,14 @@ impl Monkey {
         Monkey {
             spawn: Vec2::ZERO,
             position: Vec2::ZERO,
+            previous_position: Vec2::ZERO,
             sides: Vec2::new(2.0, 4.0),
             velocity: Vec2::ZERO,
             bananas: Vec::new(),
             bananas_thrown: 0,
             bananas_before_rage: 7,
             next_throw: Duration::from_millis(1500),
-            ai_timer: Instant::now(),
+            ai_timer: Duration::ZERO,
             enranged: false,
             rage_velocity: Vec2::new(-15.0, 0.0),
             health: Monkey::INITIAL_HEALTH,
@@ -52,6 +56,18 @@ impl Monkey {
         }
     }
 
+    /// Puts the monkey back where it started, calm, unhurt and without bananas in the air
+    pub fn reset(&mut self, now: Duration) {
+        let spawn = self.spawn;
+        *self = Monkey {
+            spawn,
+            position: spawn,
+            previous_position: spawn,
+            ai_timer: now,
+            ..Monkey::new()
+        };
+    }
+
     pub fn right(&self) -> bool {
         if self.enranged {
             self.rage_velocity.x < 0.0
@@ -65,17 +81,22 @@ impl Monkey {
         self.bananas_thrown = 0;
     }
 
-    fn throw_banana(&mut self, displacement: Vec2) {
+    fn throw_banana(&mut self, displacement: Vec2, gravity: Vec2, rng: &mut StdRng) {
         // Random y velocity based on current health the distance from the target
         let yvel =
-            (rand::random::<f32>() * 4.0 + 2.0 * self.health as f32) + (displacement.x.abs() / 4.0);
+            (rng.gen::<f32>() * 4.0 + 2.0 * self.health as f32) + (displacement.x.abs() / 4.0);
 
         // Calculate the trajectory based on the random y velocity and distance from target
         // https://www.dummies.com/education/science/physics/calculate-the-range-of-a-projectile-fired-at-an-angle/
-        let velocity = Vec2::new(((displacement.x * -physics::GRAVITY.y) / yvel) / 2.0, yvel);
+        let velocity = Vec2::new(((displacement.x * -gravity.y) / yvel) / 2.0, yvel);
         let position = self.position
             + Vec2::new(self.sides.x / 2.0 * displacement.x.signum(), -self.sides.y / 4.0);
-        self.bananas.push(Banana { position, sides: Vec2::new(0.8, 0.4), velocity });
+        self.bananas.push(Banana {
+            position,
+            previous_position: position,
+            sides: Vec2::new(0.8, 0.4),
+            velocity,
+        });
         self.bananas_thrown += 1;
     }
 
@@ -106,25 +127,26 @@ impl Monkey {
         self.sides - Vec2::new(0.25, 0.5)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn udpate(
         &mut self,
         elapsed: f32,
+        gravity: Vec2,
+        clock: &Clock,
         target: Vec2,
-        tiles: &Vec<Tile>,
+        tiles: &TileGrid,
+        rng: &mut StdRng,
         sounds: &mut Vec<SoundEffect>,
     ) {
-        let mut rng = rand::thread_rng();
+        let ai_elapsed = clock.since(self.ai_timer);
         if self.dead() {
             // Skip
-        } else if self.enranged
-            && self.ai_timer.elapsed() >= Monkey::RAGE_DELAY * self.health as u32
-        {
+        } else if self.enranged && ai_elapsed >= Monkey::RAGE_DELAY * self.health as u32 {
             self.velocity = self.rage_velocity;
-            for t in tiles {
-                let displacement = self.velocity.signum() * Vec2::X / 2.0;
-                if physics::collides(self.position + displacement, self.sides, t.position, t.sides)
-                {
-                    self.ai_timer += self.ai_timer.elapsed();
+            let next_position = self.position + self.velocity.signum() * Vec2::X / 2.0;
+            for t in tiles.near(next_position, self.sides).filter(|t| t.solid()) {
+                if physics::collides(next_position, self.sides, t.position, t.sides) {
+                    self.ai_timer = clock.now();
                     self.enranged = false;
                     self.velocity = Vec2::ZERO;
                     self.rage_velocity = -self.rage_velocity;
@@ -135,20 +157,20 @@ impl Monkey {
             self.rage();
             sounds.push(SoundEffect::Rage);
             self.bananas_before_rage = rng.gen_range(5..10);
-        } else if self.ai_timer.elapsed() > self.next_throw {
-            self.ai_timer += self.ai_timer.elapsed();
+        } else if ai_elapsed > self.next_throw {
+            self.ai_timer = clock.now();
             self.next_throw = Duration::from_millis(rng.gen_range(1000..2000));
             self.anim_timer = 0;
             let displacement = target - self.position;
             if displacement.x.abs() < Monkey::BANANA_MAX_DISTANCE {
-                self.throw_banana(displacement);
+                self.throw_banana(displacement, gravity, rng);
                 sounds.push(SoundEffect::Banana);
             }
         }
 
         self.position += self.velocity * elapsed;
 
-        match self.next_throw.checked_sub(self.ai_timer.elapsed()) {
+        match self.next_throw.checked_sub(clock.since(self.ai_timer)) {
             Some(d) if d <= Duration::from_millis(50 * 4) && !self.enranged => {
                 let col = (self.anim_timer / 50 % 4) * 128;
                 self.sprite = (col, 0, 128, 256);
@@ -163,7 +185,7 @@ impl Monkey {
         }
 
         for b in &mut self.bananas {
-            b.velocity += physics::GRAVITY * elapsed;
+            b.velocity += gravity * elapsed;
             b.position += b.velocity * elapsed;
         }
     }
@@ -172,6 +194,7 @@ impl Monkey {
 #[derive(Debug)]
 pub struct Banana {
     pub position: Vec2,
+    pub previous_position: Vec2,
     pub sides: Vec2,
     velocity: Vec2,
 }
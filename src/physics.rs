@@ -8,3 +8,121 @@ pub fn collides(pos1: Vec2, rect1: Vec2, pos2: Vec2, rect2: Vec2) -> bool {
     (pos1.x - pos2.x).abs() < (rect1.x + rect2.x) / 2.0
         && (pos1.y - pos2.y).abs() < (rect1.y + rect2.y) / 2.0
 }
+
+/// First contact of a rectangle moving along a displacement with a static one
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    /// Fraction of the displacement travelled before the contact, from 0 to 1
+    pub time: f32,
+    /// Normal of the surface that was hit, pointing away from it
+    pub normal: Vec2,
+    /// Position of the moving rectangle at the contact, flush against the surface
+    pub position: Vec2,
+}
+
+// Entry and exit times of a moving point through the slab between min and max
+fn slab(start: f32, delta: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+    if delta > 0.0 {
+        Some(((min - start) / delta, (max - start) / delta))
+    } else if delta < 0.0 {
+        Some(((max - start) / delta, (min - start) / delta))
+    } else if start > min && start < max {
+        Some((f32::NEG_INFINITY, f32::INFINITY))
+    } else {
+        None
+    }
+}
+
+/// Sweeps the first rectangle along the displacement against the second one. Rectangles
+/// already overlapping or just touching without moving towards each other don't collide.
+pub fn sweep(
+    pos1: Vec2,
+    rect1: Vec2,
+    displacement: Vec2,
+    pos2: Vec2,
+    rect2: Vec2,
+) -> Option<Contact> {
+    // Sweeping a point against the rectangles combined is the same as sweeping both
+    let half = (rect1 + rect2) / 2.0;
+    let (min, max) = (pos2 - half, pos2 + half);
+    let (entry_x, exit_x) = slab(pos1.x, displacement.x, min.x, max.x)?;
+    let (entry_y, exit_y) = slab(pos1.y, displacement.y, min.y, max.y)?;
+
+    let entry = entry_x.max(entry_y);
+    if entry > exit_x.min(exit_y) || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+
+    let mut position = pos1 + displacement * entry;
+    let normal = if entry_x > entry_y {
+        let normal = Vec2::new(-displacement.x.signum(), 0.0);
+        position.x = pos2.x + normal.x * half.x;
+        normal
+    } else {
+        let normal = Vec2::new(0.0, -displacement.y.signum());
+        position.y = pos2.y + normal.y * half.y;
+        normal
+    };
+    Some(Contact { time: entry, normal, position })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT: Vec2 = const_vec2!([1.0, 1.0]);
+
+    #[test]
+    fn hits_along_an_axis() {
+        let contact = sweep(Vec2::ZERO, UNIT, Vec2::new(4.0, 0.0), Vec2::new(3.0, 0.0), UNIT);
+        let contact = contact.unwrap();
+        assert_eq!(contact.time, 0.5);
+        assert_eq!(contact.normal, Vec2::new(-1.0, 0.0));
+        assert_eq!(contact.position, Vec2::new(2.0, 0.0));
+
+        let landing = sweep(Vec2::new(0.0, 3.0), UNIT, Vec2::new(0.0, -4.0), Vec2::ZERO, UNIT);
+        let landing = landing.unwrap();
+        assert_eq!(landing.time, 0.5);
+        assert_eq!(landing.normal, Vec2::Y);
+        assert_eq!(landing.position, Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn misses() {
+        // Too short, passing by and moving away
+        assert!(sweep(Vec2::ZERO, UNIT, Vec2::new(1.5, 0.0), Vec2::new(3.0, 0.0), UNIT).is_none());
+        assert!(sweep(Vec2::ZERO, UNIT, Vec2::new(4.0, 0.0), Vec2::new(3.0, 2.0), UNIT).is_none());
+        assert!(sweep(Vec2::ZERO, UNIT, Vec2::new(-4.0, 0.0), Vec2::new(3.0, 0.0), UNIT).is_none());
+        // Sliding along a surface it is touching
+        assert!(sweep(Vec2::new(0.0, 1.0), UNIT, Vec2::new(4.0, 0.0), Vec2::ZERO, UNIT).is_none());
+    }
+
+    #[test]
+    fn corner_hits_pick_the_last_axis_entered() {
+        // Reaches the top of the box before its side, so it lands on it
+        let contact = sweep(Vec2::new(-2.0, 2.5), UNIT, Vec2::new(2.0, -2.0), Vec2::ZERO, UNIT);
+        let contact = contact.unwrap();
+        assert_eq!(contact.normal, Vec2::Y);
+        assert_eq!(contact.position.y, 1.0);
+
+        // Reaches its side last, so it is stopped by it
+        let contact = sweep(Vec2::new(-2.5, 2.0), UNIT, Vec2::new(2.0, -2.0), Vec2::ZERO, UNIT);
+        let contact = contact.unwrap();
+        assert_eq!(contact.normal, Vec2::new(-1.0, 0.0));
+        assert_eq!(contact.position.x, -1.0);
+    }
+
+    #[test]
+    fn overlapping_rectangles_are_ignored() {
+        // Dropping through a one-way platform starts inside it
+        let inside = Vec2::new(0.2, 0.3);
+        assert!(sweep(inside, UNIT, Vec2::new(0.0, -2.0), Vec2::ZERO, UNIT).is_none());
+        assert!(sweep(inside, UNIT, Vec2::new(1.0, 1.0), Vec2::ZERO, UNIT).is_none());
+    }
+
+    #[test]
+    fn overlaps() {
+        assert!(collides(Vec2::ZERO, UNIT, Vec2::new(0.9, 0.9), UNIT));
+        assert!(!collides(Vec2::ZERO, UNIT, Vec2::new(1.0, 0.0), UNIT));
+    }
+}
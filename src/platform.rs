@@ -0,0 +1,83 @@
+use glam::Vec2;
+
+/// Platform going through a path of waypoints, carrying whatever stands on it.
+/// It can be jumped up through from below like the one-way platform tiles.
+#[derive(Debug)]
+pub struct MovingPlatform {
+    pub position: Vec2,
+    pub previous_position: Vec2,
+    pub sides: Vec2,
+    /// Distance moved in the last update
+    pub displacement: Vec2,
+    path: Vec<Vec2>,
+    looping: bool,
+    target: usize,
+    forward: bool,
+}
+
+impl MovingPlatform {
+    /// Width in tiles
+    pub const WIDTH: usize = 3;
+    // Tiles per second
+    const SPEED: f32 = 4.0;
+    // Gap between the bottom of an object and the top of the platform still counted as
+    // standing on it
+    const TOLERANCE: f32 = 0.01;
+
+    pub fn new(path: Vec<Vec2>, looping: bool, sides: Vec2) -> MovingPlatform {
+        MovingPlatform {
+            position: path[0],
+            previous_position: path[0],
+            sides,
+            displacement: Vec2::ZERO,
+            path,
+            looping,
+            target: 1,
+            forward: true,
+        }
+    }
+
+    fn next_target(&mut self) {
+        if self.looping {
+            self.target = (self.target + 1) % self.path.len();
+            return;
+        }
+        if self.target == self.path.len() - 1 {
+            self.forward = false;
+        } else if self.target == 0 {
+            self.forward = true;
+        }
+        if self.forward {
+            self.target += 1;
+        } else {
+            self.target -= 1;
+        }
+    }
+
+    pub fn update(&mut self, elapsed: f32) {
+        let start = self.position;
+        let mut step = MovingPlatform::SPEED * elapsed;
+        // Waypoints reached in the middle of an update are left behind with the distance
+        // that is left, bounded so a path of repeated waypoints can't loop forever
+        for _ in 0..self.path.len() {
+            let to_target = self.path[self.target] - self.position;
+            let distance = to_target.length();
+            if distance > step {
+                self.position += to_target / distance * step;
+                break;
+            }
+            self.position = self.path[self.target];
+            step -= distance;
+            self.next_target();
+        }
+        self.displacement = self.position - start;
+    }
+
+    /// Whether an object was standing on the platform before its last update
+    pub fn carries(&self, position: Vec2, sides: Vec2) -> bool {
+        let top = self.previous_position.y + self.sides.y / 2.0;
+        let bottom = position.y - sides.y / 2.0;
+        (position.x - self.previous_position.x).abs() < (sides.x + self.sides.x) / 2.0
+            && (bottom - top).abs() < MovingPlatform::TOLERANCE
+    }
+}
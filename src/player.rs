@@ -1,10 +1,12 @@
 use glam::{const_vec2, Vec2};
-use sdl2::keyboard::Keycode;
-use std::collections::HashSet;
-use std::time::Instant;
+use std::collections::{HashSet, VecDeque};
 
-use crate::level::Tile;
+use crate::clock::Clock;
+use crate::header::Ability;
+use crate::input::Action;
+use crate::level::{TileGrid, TileKind};
 use crate::physics;
+use crate::platform::MovingPlatform;
 use crate::sound::SoundEffect;
 
 const PLAYER_SPEED: f32 = 30.0;
@@ -12,42 +14,196 @@ const JUMP_SPEED: f32 = 15.0;
 
 const MAX_VELOCITY: Vec2 = const_vec2!([10.0, 100.0]);
 
+// Running faster while sprinting, up to a higher top speed
+const SPRINT_FACTOR: f32 = 1.5;
+const SPRINT_VELOCITY: f32 = 15.0;
+
+// Default time windows in seconds, see Player::coyote_time and Player::jump_buffer
+const COYOTE_TIME: f32 = 0.1;
+const JUMP_BUFFER: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeathCause {
+    Fall,
+    Timeout,
+    Enemy,
+    Monkey,
+    Banana,
+    Spikes,
+    Lava,
+}
+
 #[derive(Debug)]
 pub struct Player {
     pub spawn: Vec2,
     pub position: Vec2,
+    // Position at the start of the last update, drawn interpolated with the current one
+    pub previous_position: Vec2,
     pub sides: Vec2,
     pub velocity: Vec2,
     pub dead: bool,
-    grounded: bool,
+    pub death_cause: Option<DeathCause>,
+    // Seconds left of the death animation
+    death_timer: f32,
+    /// Hits left before losing a life
+    pub health: u32,
+    /// Lives left, the game is over when the last one is lost
+    pub lives: u32,
+    // Seconds left of invulnerability after being hit or respawning
+    invulnerable: f32,
+    /// Seconds after walking off a ledge the player can still jump
+    pub coyote_time: f32,
+    /// Seconds a jump pressed in the air is remembered, so it happens on landing
+    pub jump_buffer: f32,
+    // Seconds left to jump since the player was last on the ground
+    coyote: f32,
+    // Seconds left for a jump pressed before landing
+    buffered_jump: f32,
+    jump_held: bool,
+    /// Moves allowed in the level on top of walking and jumping
+    pub abilities: HashSet<Ability>,
+    // Side of the wall the player is touching in the air, -1.0 on the left and 1.0 on the
+    // right
+    wall: Option<f32>,
+    sliding: bool,
+    // Jumps left before landing again
+    air_jumps: u32,
+    // Seconds left of the somersault after a double jump and of the kick off a wall
+    spin: f32,
+    kick: f32,
+    sprinting: bool,
+    // Seconds left of the dash and before the next one
+    dash: f32,
+    dash_cooldown: f32,
+    dash_held: bool,
+    /// Latest positions of the player while moving fast, newest last
+    pub trail: VecDeque<Vec2>,
+    // Kind of tile the player is standing on
+    ground: Option<TileKind>,
     crouched: bool,
     pub sprite: (i32, i32, u32, u32),
-    timer: Instant,
 }
 
 impl Player {
+    /// Hits taken before losing a life
+    pub const MAX_HEALTH: u32 = 3;
+    /// Lives at the start of a playthrough
+    pub const LIVES: u32 = 3;
+    // Surfaces the player can slide along in a single update
+    const MAX_CONTACTS: usize = 3;
+    // Highest change in the ground followed while walking, on slopes or down small steps
+    const STEP_HEIGHT: f32 = 0.5;
+    // Margin under the top of the side of a slope still walked over instead of blocking
+    const SLOPE_EDGE: f32 = 0.001;
+    // Seconds the hazard deaths are shown before the game is over
+    const DEATH_ANIMATION: f32 = 1.5;
+    // Tiles per second
+    const LAVA_SINK_SPEED: f32 = 0.4;
+    // Seconds without taking damage after a hit
+    const INVULNERABILITY: f32 = 2.0;
+    // Velocity the player is thrown back with when hit
+    const KNOCKBACK: Vec2 = const_vec2!([8.0, 8.0]);
+    // Fraction of the gravity pulling the player down while sliding down a wall, and the
+    // fastest it slides in tiles per second
+    const WALL_SLIDE_GRAVITY: f32 = 0.25;
+    const WALL_SLIDE_SPEED: f32 = 4.0;
+    // Velocity the player leaves a wall with, pointing away from it
+    const WALL_JUMP: Vec2 = const_vec2!([10.0, 15.0]);
+    // Gap to a wall still counted as touching it
+    const WALL_REACH: f32 = 0.05;
+    // Seconds the double jump somersault and the wall jump kick are shown
+    const SPIN_TIME: f32 = 0.3;
+    const KICK_TIME: f32 = 0.2;
+    // Horizontal speed of a dash in tiles per second, how long it lasts and the seconds
+    // before the next one
+    const DASH_SPEED: f32 = 25.0;
+    const DASH_TIME: f32 = 0.15;
+    const DASH_COOLDOWN: f32 = 0.75;
+    // Positions kept for the trail
+    const TRAIL_LENGTH: usize = 5;
+
     pub fn new() -> Player {
         Player {
             spawn: Vec2::ZERO,
             position: Vec2::ZERO,
+            previous_position: Vec2::ZERO,
             sides: Vec2::new(0.9, 1.8),
             velocity: Vec2::ZERO,
             dead: false,
-            grounded: false,
+            death_cause: None,
+            death_timer: 0.0,
+            health: Player::MAX_HEALTH,
+            lives: Player::LIVES,
+            invulnerable: 0.0,
+            coyote_time: COYOTE_TIME,
+            jump_buffer: JUMP_BUFFER,
+            coyote: 0.0,
+            buffered_jump: 0.0,
+            jump_held: false,
+            abilities: HashSet::new(),
+            wall: None,
+            sliding: false,
+            air_jumps: 0,
+            spin: 0.0,
+            kick: 0.0,
+            sprinting: false,
+            dash: 0.0,
+            dash_cooldown: 0.0,
+            dash_held: false,
+            trail: VecDeque::new(),
+            ground: None,
             crouched: false,
             sprite: (0, 0, 128, 256),
-            timer: Instant::now(),
         }
     }
 
+    /// Farthest gap and highest ledge a sprinting jump can clear under the given gravity,
+    /// with a dash at the top of it. A second jump at the top of the first one doubles the
+    /// distance jumped.
+    pub fn jump_reach(gravity: Vec2, abilities: &HashSet<Ability>) -> Vec2 {
+        // Running speed settles where the acceleration is cancelled by the drag
+        let speed = (PLAYER_SPEED * SPRINT_FACTOR / physics::DRAG).min(SPRINT_VELOCITY);
+        let g = -gravity.y;
+        let jump = Vec2::new(speed * 2.0 * JUMP_SPEED / g, JUMP_SPEED * JUMP_SPEED / (2.0 * g));
+        let jump = if abilities.contains(&Ability::DoubleJump) { jump * 2.0 } else { jump };
+        // A dash only gains its extra speed over the sprint, as it stops the jump's rise
+        // and jumping during one ends it
+        jump + Vec2::X * (Player::DASH_SPEED - speed) * Player::DASH_TIME
+    }
+
     fn grounded(&self) -> bool {
-        self.grounded
+        self.ground.is_some()
     }
 
     pub fn sides(&self) -> Vec2 {
         self.sides
     }
 
+    fn can(&self, ability: Ability) -> bool {
+        self.abilities.contains(&ability)
+    }
+
+    /// Whether the sprite is drawn mirrored, facing left
+    pub fn facing_left(&self) -> bool {
+        // The wall slide sprite faces away from a wall on the right
+        if self.sliding {
+            self.wall == Some(-1.0)
+        } else {
+            self.velocity.x < 0.0
+        }
+    }
+
+    // Side of the solid tile the player is touching, if any
+    fn wall_side(&self, tiles: &TileGrid) -> Option<f32> {
+        [-1.0, 1.0].iter().copied().find(|side| {
+            let probe = self.position + Vec2::X * *side * Player::WALL_REACH;
+            tiles
+                .near(probe, self.sides)
+                .filter(|t| t.solid())
+                .any(|t| physics::collides(probe, self.sides, t.position, t.sides))
+        })
+    }
+
     pub fn attack(&mut self, position: Vec2, sides: Vec2) -> bool {
         let (foot_pos, foot_rect) = self.foot_rect();
         let attacked =
@@ -58,34 +214,139 @@ impl Player {
         attacked
     }
 
+    // Jumping also ends the dash, slowing back down to the running speed
     fn jump(&mut self) {
         self.velocity.y = JUMP_SPEED;
+        self.coyote = 0.0;
+        self.buffered_jump = 0.0;
+        self.dash = 0.0;
     }
 
     fn speed(&self) -> f32 {
-        if self.grounded() {
-            PLAYER_SPEED
+        match (self.grounded(), self.sprinting) {
+            (true, true) => PLAYER_SPEED * SPRINT_FACTOR,
+            (true, false) => PLAYER_SPEED,
+            _ => PLAYER_SPEED / 4.0,
+        }
+    }
+
+    /// Top speed, overridden while dashing and sprinting
+    fn max_velocity(&self) -> Vec2 {
+        if self.dashing() {
+            Vec2::new(Player::DASH_SPEED, MAX_VELOCITY.y)
+        } else if self.sprinting {
+            Vec2::new(SPRINT_VELOCITY, MAX_VELOCITY.y)
         } else {
-            PLAYER_SPEED / 4.0
+            MAX_VELOCITY
         }
     }
 
     fn accelerate(&mut self, vel: Vec2, elapsed: f32) {
+        let max_velocity = self.max_velocity();
         self.velocity += vel * elapsed;
-        self.velocity = self.velocity.clamp(-MAX_VELOCITY, MAX_VELOCITY);
+        self.velocity = self.velocity.clamp(-max_velocity, max_velocity);
+    }
+
+    pub fn dashing(&self) -> bool {
+        self.dash > 0.0
     }
 
     fn apply_drag(&mut self, elapsed: f32) {
-        let drag = if self.grounded() { physics::DRAG } else { 0.0 };
+        let drag = if self.grounded() && !self.dashing() { physics::DRAG } else { 0.0 };
         self.accelerate(Vec2::new(-drag * self.velocity.x, 0.0), elapsed);
         if self.velocity.x.abs() < 0.1 {
             self.velocity.x = 0.0;
         }
     }
 
-    pub fn die(&mut self) {
+    /// Loses a life, or all of them when running out of time
+    pub fn die(&mut self, cause: DeathCause) {
         self.dead = true;
+        self.death_cause = Some(cause);
+        self.crouched = false;
+        self.lives = if cause == DeathCause::Timeout { 0 } else { self.lives.saturating_sub(1) };
+        // Lava swallows the player, falling keeps it going and anything else throws it up
+        // before it falls off the screen
+        (self.velocity, self.death_timer) = match cause {
+            DeathCause::Timeout => (Vec2::ZERO, 0.0),
+            DeathCause::Lava => (-Vec2::Y * Player::LAVA_SINK_SPEED, Player::DEATH_ANIMATION),
+            DeathCause::Fall => (self.velocity, Player::DEATH_ANIMATION),
+            _ => (Vec2::Y * JUMP_SPEED, Player::DEATH_ANIMATION),
+        };
+    }
+
+    /// Takes a hit from something at a position, throwing the player away from it. Nothing
+    /// hurts the player while invulnerable.
+    pub fn hurt(&mut self, cause: DeathCause, from: Vec2, sounds: &mut Vec<SoundEffect>) {
+        if self.dead || self.invulnerable > 0.0 {
+            return;
+        }
+        self.health = self.health.saturating_sub(1);
+        if self.health == 0 {
+            self.die(cause);
+            sounds.push(SoundEffect::Dead);
+        } else {
+            let away = if self.position.x < from.x { -1.0 } else { 1.0 };
+            self.velocity = Player::KNOCKBACK * Vec2::new(away, 1.0);
+            self.invulnerable = Player::INVULNERABILITY;
+            sounds.push(SoundEffect::Hit);
+        }
+    }
+
+    /// Seconds left of invulnerability
+    pub fn invulnerable(&self) -> f32 {
+        self.invulnerable
+    }
+
+    /// Whether the player is dead but the death animation is still playing
+    pub fn dying(&self) -> bool {
+        self.dead && self.death_timer > 0.0
+    }
+
+    /// Whether the last life was lost and its death animation is over
+    pub fn game_over(&self) -> bool {
+        self.dead && !self.dying() && self.lives == 0
+    }
+
+    /// Brings the player back to life at the spawn point with full health, nothing
+    /// of the previous life carried over
+    pub fn respawn(&mut self) {
+        self.position = self.spawn;
+        self.previous_position = self.spawn;
         self.velocity = Vec2::ZERO;
+        self.dead = false;
+        self.death_cause = None;
+        self.health = Player::MAX_HEALTH;
+        self.invulnerable = Player::INVULNERABILITY;
+        self.ground = None;
+        self.coyote = 0.0;
+        self.buffered_jump = 0.0;
+        self.jump_held = false;
+        self.wall = None;
+        self.sliding = false;
+        self.air_jumps = 0;
+        self.spin = 0.0;
+        self.kick = 0.0;
+        self.sprinting = false;
+        self.dash = 0.0;
+        self.dash_cooldown = 0.0;
+        self.dash_held = false;
+        self.trail.clear();
+        self.crouched = false;
+    }
+
+    /// Moves the player through its death animation, going through the tiles
+    pub fn update_death(&mut self, elapsed: f32, gravity: Vec2) {
+        self.death_timer = (self.death_timer - elapsed).max(0.0);
+        if self.death_cause != Some(DeathCause::Lava) {
+            self.velocity += gravity * elapsed;
+            self.sprite = (0, 512, 128, 256);
+        } else {
+            // Flails the arms alternating between the jump and crouch frames
+            let col = (self.death_timer * 10.0) as i32 % 2 * 128;
+            self.sprite = (col, 512, 128, 256);
+        }
+        self.position += self.velocity * elapsed;
     }
 
     pub fn foot_rect(&self) -> (Vec2, Vec2) {
@@ -109,89 +370,240 @@ impl Player {
         }
     }
 
+    /// Moves the player along with the platform it stands on, stopping at walls
+    pub fn carry(&mut self, displacement: Vec2, tiles: &TileGrid) {
+        let contact = tiles
+            .near(self.position + displacement / 2.0, self.sides + displacement.abs())
+            .filter(|t| t.solid())
+            .filter_map(|t| {
+                physics::sweep(self.position, self.sides, displacement, t.position, t.sides)
+            })
+            .min_by(|a, b| a.time.total_cmp(&b.time));
+        self.position = contact.map_or(self.position + displacement, |c| c.position);
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
-        keys: &HashSet<Keycode>,
+        actions: &HashSet<Action>,
         elapsed: f32,
-        tiles: &Vec<Tile>,
+        gravity: Vec2,
+        clock: &Clock,
+        tiles: &TileGrid,
+        platforms: &[MovingPlatform],
         sounds: &mut Vec<SoundEffect>,
     ) {
+        self.invulnerable = (self.invulnerable - elapsed).max(0.0);
+        self.coyote =
+            if self.grounded() { self.coyote_time } else { (self.coyote - elapsed).max(0.0) };
+        self.buffered_jump = (self.buffered_jump - elapsed).max(0.0);
+        self.spin = (self.spin - elapsed).max(0.0);
+        self.kick = (self.kick - elapsed).max(0.0);
+        self.dash = (self.dash - elapsed).max(0.0);
+        self.dash_cooldown = (self.dash_cooldown - elapsed).max(0.0);
+        self.sprinting = actions.contains(&Action::Sprint);
+        if self.grounded() {
+            self.air_jumps = 1;
+        }
+
         // Drag
         self.apply_drag(elapsed);
 
-        self.crouched = keys.contains(&Keycode::Down);
-        // Input
-        for key in keys {
-            match key {
-                Keycode::Left => {
-                    if !self.crouched {
-                        self.accelerate(Vec2::new(-self.speed(), 0.0), elapsed);
-                    }
-                }
-                Keycode::Right => {
-                    if !self.crouched {
-                        self.accelerate(Vec2::new(self.speed(), 0.0), elapsed);
-                    }
-                }
-                Keycode::Space => {
-                    if self.grounded() {
-                        self.jump();
-                        sounds.push(SoundEffect::Jump);
-                    }
-                }
-                _ => {}
-            }
+        self.crouched = actions.contains(&Action::Crouch);
+        let pushing = |side: f32| {
+            let action = if side < 0.0 { Action::MoveLeft } else { Action::MoveRight };
+            actions.contains(&action)
+        };
+
+        // Input, with opposite directions cancelling out so the order of the actions
+        // never changes the outcome
+        let direction = match (pushing(-1.0), pushing(1.0)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        };
+        if direction != 0.0 && !self.crouched {
+            self.accelerate(Vec2::new(direction * self.speed(), 0.0), elapsed);
+        }
+        // Jumping while crouched on a one-way platform drops down through it
+        let drop_through = actions.contains(&Action::Jump)
+            && self.crouched
+            && self.ground == Some(TileKind::OneWay);
+        if drop_through {
+            self.coyote = 0.0;
+            self.buffered_jump = 0.0;
+        }
+
+        // Dashes straight ahead, or the way the player is pushing
+        let dash_held = actions.contains(&Action::Dash);
+        if dash_held && !self.dash_held && self.dash_cooldown <= 0.0 && !self.crouched {
+            let direction = if direction != 0.0 {
+                direction
+            } else if self.facing_left() {
+                -1.0
+            } else {
+                1.0
+            };
+            self.dash = Player::DASH_TIME;
+            self.dash_cooldown = Player::DASH_COOLDOWN;
+            self.velocity = Vec2::X * direction * Player::DASH_SPEED;
+            sounds.push(SoundEffect::Dash);
+        }
+        self.dash_held = dash_held;
+
+        // Jumps are still taken shortly after walking off a ledge, and pressing jump shortly
+        // before landing jumps as soon as the player lands
+        let jump_held = actions.contains(&Action::Jump);
+        let jump_pressed = jump_held && !self.jump_held;
+        if jump_pressed {
+            self.buffered_jump = self.jump_buffer;
+        }
+        self.jump_held = jump_held;
+        let can_jump = self.grounded() || self.coyote > 0.0;
+        let wall_jump = self.wall.filter(|_| self.can(Ability::WallJump));
+        let double_jump = jump_pressed && self.air_jumps > 0 && self.can(Ability::DoubleJump);
+        if !drop_through && can_jump && (jump_held || self.buffered_jump > 0.0) {
+            self.jump();
+            sounds.push(SoundEffect::Jump);
+        } else if let Some(side) = wall_jump.filter(|_| jump_pressed || self.buffered_jump > 0.0) {
+            self.jump();
+            self.velocity = Player::WALL_JUMP * Vec2::new(-side, 1.0);
+            self.air_jumps = 1;
+            self.kick = Player::KICK_TIME;
+            sounds.push(SoundEffect::WallJump);
+        } else if double_jump && !drop_through {
+            self.jump();
+            self.air_jumps -= 1;
+            self.spin = Player::SPIN_TIME;
+            sounds.push(SoundEffect::DoubleJump);
         }
 
         // Jump higher if key is held
-        if !keys.contains(&Keycode::Space) && self.velocity.y > 0.0 {
+        if !actions.contains(&Action::Jump) && self.velocity.y > 0.0 {
             self.velocity.y = self.velocity.y.min(JUMP_SPEED / 2.0);
         }
 
-        // Gravity
-        self.accelerate(physics::GRAVITY, elapsed);
+        // Slides down slowly while pushing against a wall
+        let was_sliding = self.sliding;
+        self.sliding =
+            self.can(Ability::WallSlide) && self.velocity.y < 0.0 && self.wall.is_some_and(pushing);
+        if self.sliding && !was_sliding {
+            sounds.push(SoundEffect::WallSlide);
+        }
+
+        // Gravity, which the dash ignores
+        if self.dashing() {
+            self.velocity.y = 0.0;
+        } else if self.sliding {
+            self.accelerate(gravity * Player::WALL_SLIDE_GRAVITY, elapsed);
+            self.velocity.y = self.velocity.y.max(-Player::WALL_SLIDE_SPEED);
+        } else {
+            self.accelerate(gravity, elapsed);
+        }
 
         let mut displacement = self.velocity * elapsed;
 
-        self.grounded = false;
-        // Check for collisions
-        for t in tiles {
-            // Check X component
-            let x_collision = physics::collides(
-                self.position + displacement * Vec2::X,
-                self.sides,
-                t.position,
-                t.sides,
-            );
-
-            if x_collision {
+        let start = self.position;
+        let was_grounded = self.grounded();
+        self.ground = None;
+        // Move until the first contact and slide along the surface with what is left, so
+        // long frames can't skip over thin tiles and the player ends flush against them
+        for _ in 0..Player::MAX_CONTACTS {
+            let reach = self.sides + displacement.abs();
+            // Moving platforms block the same way as one-way tiles
+            let surfaces = tiles
+                .near(self.position + displacement / 2.0, reach)
+                .map(|t| (t.position, t.sides, t.kind))
+                .chain(platforms.iter().map(|p| (p.position, p.sides, TileKind::OneWay)));
+            let contact = surfaces
+                .filter(|&(_, _, kind)| kind != TileKind::OneWay || !drop_through)
+                .filter_map(|(position, sides, kind)| {
+                    let contact =
+                        physics::sweep(self.position, self.sides, displacement, position, sides)?;
+                    let top = position.y + sides.y / 2.0;
+                    match kind {
+                        TileKind::Solid => Some((contact, kind, top)),
+                        // One-way platforms only stop the player when landing on them
+                        TileKind::OneWay => {
+                            (contact.normal.y > 0.0).then_some((contact, kind, top))
+                        }
+                        // Slopes block with their bottom and their sides as high as they go, the
+                        // player is put back on top of them afterwards
+                        TileKind::Slope { left, right } => {
+                            let height = if contact.normal.x < 0.0 { left } else { right };
+                            let side = top - (1.0 - height) * sides.y;
+                            let feet = contact.position.y - self.sides.y / 2.0;
+                            let wall = contact.normal.x != 0.0 && feet < side - Player::SLOPE_EDGE;
+                            (wall || contact.normal.y < 0.0).then_some((contact, kind, side))
+                        }
+                    }
+                })
+                .min_by(|a, b| a.0.time.total_cmp(&b.0.time));
+
+            let (contact, kind, top) = match contact {
+                Some(contact) => contact,
+                None => {
+                    self.position += displacement;
+                    break;
+                }
+            };
+            self.position = contact.position;
+            displacement *= 1.0 - contact.time;
+            let rise = top - (self.position.y - self.sides.y / 2.0);
+            if contact.normal.x != 0.0 && was_grounded && rise <= Player::STEP_HEIGHT {
+                // Steps up onto low ledges, like the tiles at the top of slopes
+                self.position.y += rise;
+            } else if contact.normal.x != 0.0 {
                 displacement.x = 0.0;
                 self.velocity.x = 0.0;
-            }
-
-            // Check Y component
-            let y_collision = physics::collides(
-                self.position + displacement * Vec2::Y,
-                self.sides,
-                t.position,
-                t.sides,
-            );
-
-            if y_collision {
-                // Going down
-                if self.velocity.y < 0.0 {
-                    self.grounded = true;
+            } else {
+                // Landed on the floor
+                if contact.normal.y > 0.0 {
+                    self.ground = Some(kind);
                 }
                 displacement.y = 0.0;
                 self.velocity.y = 0.0;
             }
         }
-        // Apply new Position
-        self.position += displacement;
 
-        let col: i32 = (self.timer.elapsed().as_millis() as i32 / 160 % 4) * 128;
+        // Lifts the player onto the slopes it moved into and, while walking, keeps it on the
+        // ground going down slopes and steps instead of falling a bit every update
+        if self.velocity.y <= 0.0 && !drop_through {
+            let below = if was_grounded { Player::STEP_HEIGHT } else { 0.0 };
+            let above = Player::STEP_HEIGHT + (start.y - self.position.y).max(0.0);
+            let ground = tiles
+                .ground(self.position, self.sides, below, above, |t| was_grounded || t.sloped());
+            if let Some((top, kind)) = ground {
+                self.position.y = top + self.sides.y / 2.0;
+                self.velocity.y = 0.0;
+                self.ground = Some(kind);
+            }
+        }
+
+        self.wall = if self.grounded() { None } else { self.wall_side(tiles) };
+        self.sliding &= self.wall.is_some();
+
+        // The trail follows the player while dashing or sprinting at full speed, and fades
+        // away one position per update otherwise
+        let fast = self.velocity.x.abs() > MAX_VELOCITY.x;
+        if self.dashing() || fast {
+            self.trail.push_back(self.position);
+        }
+        if self.trail.len() > Player::TRAIL_LENGTH || !self.dashing() && !fast {
+            self.trail.pop_front();
+        }
+
+        let col: i32 = (clock.now().as_millis() as i32 / 160 % 4) * 128;
         if self.crouched {
             self.sprite = (128, 512, 128, 256);
+        } else if self.sliding {
+            self.sprite = (0, 768, 128, 256);
+        } else if self.kick > 0.0 {
+            self.sprite = (128, 768, 128, 256);
+        } else if self.spin > 0.0 {
+            // Somersault alternating between the tucked frames
+            let col = 256 + (self.spin * 20.0) as i32 % 2 * 128;
+            self.sprite = (col, 768, 128, 256);
         } else if !self.grounded() {
             self.sprite = (0, 512, 128, 256);
         } else if self.velocity.x.abs() > 0.0 {
@@ -2,14 +2,21 @@ use glam::Vec2;
 use sdl2::image::LoadTexture;
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
-use sdl2::render::TextureCreator;
+use sdl2::render::{BlendMode, TextureCreator};
 use sdl2::render::{Texture, TextureQuery, WindowCanvas};
 use sdl2::ttf::Font;
 use sdl2::video::WindowContext;
+use std::time::Duration;
 
-use crate::level::Level;
+use crate::campaign::Campaign;
+use crate::editor::{Editor, BRUSHES};
+use crate::level::{Hazard, HazardKind, Level, Tile};
+use crate::platform::MovingPlatform;
+use crate::player::{DeathCause, Player};
+use crate::state::{GameState, Menu, Settings};
 
 pub struct TextureManager<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
     jeff: Texture<'a>,
     monkey: Texture<'a>,
     banana: Texture<'a>,
@@ -19,11 +26,16 @@ pub struct TextureManager<'a> {
     endgame: Texture<'a>,
     decor: Vec<Texture<'a>>,
     backgrounds: Vec<Texture<'a>>,
+    background_files: Vec<String>,
     enemies: Vec<Texture<'a>>,
 }
 
 impl<'a> TextureManager<'a> {
-    pub fn load(texture_creator: &TextureCreator<WindowContext>) -> Result<TextureManager, String> {
+    const BACKGROUNDS: [&'static str; 2] = ["assets/background1.png", "assets/background2.png"];
+
+    pub fn load(
+        texture_creator: &TextureCreator<WindowContext>,
+    ) -> Result<TextureManager<'_>, String> {
         let jeff = texture_creator.load_texture("assets/jeff.png")?;
         let monkey = texture_creator.load_texture("assets/monkey.png")?;
         let banana = texture_creator.load_texture("assets/banana.png")?;
@@ -38,10 +50,12 @@ impl<'a> TextureManager<'a> {
             texture_creator.load_texture("assets/orchid2.png")?,
         ];
 
-        let backgrounds = vec![
-            texture_creator.load_texture("assets/background1.png")?,
-            texture_creator.load_texture("assets/background2.png")?,
-        ];
+        let background_files: Vec<String> =
+            TextureManager::BACKGROUNDS.iter().map(|s| s.to_string()).collect();
+        let backgrounds = background_files
+            .iter()
+            .map(|f| texture_creator.load_texture(f))
+            .collect::<Result<_, _>>()?;
 
         let enemies = vec![
             texture_creator.load_texture("assets/andi.png")?,
@@ -60,6 +74,7 @@ impl<'a> TextureManager<'a> {
         ];
 
         Ok(TextureManager {
+            texture_creator,
             jeff,
             monkey,
             banana,
@@ -69,9 +84,35 @@ impl<'a> TextureManager<'a> {
             endgame,
             decor,
             backgrounds,
+            background_files,
             enemies,
         })
     }
+
+    /// Loads every texture again after they changed on disk, keeping the current backgrounds
+    pub fn reload(&mut self) -> Result<(), String> {
+        let mut reloaded = TextureManager::load(self.texture_creator)?;
+        reloaded.set_backgrounds(&self.background_files)?;
+        *self = reloaded;
+        Ok(())
+    }
+
+    /// Swaps the background layers, going back to the default ones when empty
+    pub fn set_backgrounds(&mut self, files: &[String]) -> Result<(), String> {
+        let files: Vec<String> = if files.is_empty() {
+            TextureManager::BACKGROUNDS.iter().map(|s| s.to_string()).collect()
+        } else {
+            files.to_vec()
+        };
+        if files != self.background_files {
+            self.backgrounds = files
+                .iter()
+                .map(|f| self.texture_creator.load_texture(f))
+                .collect::<Result<_, _>>()?;
+            self.background_files = files;
+        }
+        Ok(())
+    }
 }
 
 pub struct TextRenderer<'a, 'r> {
@@ -81,17 +122,17 @@ pub struct TextRenderer<'a, 'r> {
 }
 
 impl<'a, 'r> TextRenderer<'a, 'r> {
-    fn render_text(&self, text: &str, font: &Font, color: Color) -> Result<Texture, String> {
+    fn render_text(&self, text: &str, font: &Font, color: Color) -> Result<Texture<'_>, String> {
         let surface = font.render(text).blended(color).map_err(|e| e.to_string())?;
 
         self.texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())
     }
 
-    fn render_text32(&self, text: &str, color: Color) -> Result<Texture, String> {
+    fn render_text32(&self, text: &str, color: Color) -> Result<Texture<'_>, String> {
         self.render_text(text, &self.font32, color)
     }
 
-    fn render_text64(&self, text: &str, color: Color) -> Result<Texture, String> {
+    fn render_text64(&self, text: &str, color: Color) -> Result<Texture<'_>, String> {
         self.render_text(text, &self.font64, color)
     }
 }
@@ -123,6 +164,17 @@ impl Camera {
         (t.0 as i32, t.1 as i32)
     }
 
+    /// Point of the world under a pixel of the screen
+    pub fn to_world(&self, (x, y): (i32, i32)) -> Vec2 {
+        let w = self.screen_size.0 as f32;
+        let h = self.screen_size.1 as f32;
+        let point = Vec2::new(
+            (x as f32 - w / 2.0) * self.bounds.x / w,
+            (h / 2.0 - y as f32) * self.bounds.y / h,
+        );
+        point + self.center
+    }
+
     pub fn recenter(&mut self, position: Vec2, level_bounds: Vec2) {
         self.center = position;
         // Clamp camera position
@@ -135,24 +187,162 @@ impl Camera {
     }
 }
 
+fn render_menu(
+    canvas: &mut WindowCanvas,
+    camera: &Camera,
+    menu: &Menu,
+    text_renderer: &TextRenderer,
+) -> Result<(), String> {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+    canvas.fill_rect(None)?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    let line_break = text_renderer.font64.recommended_line_spacing();
+    let (w, h) = camera.screen_size;
+    let mut center = Point::new(w as i32 / 2, h as i32 / 4);
+
+    let texture = text_renderer.render_text64(menu.title, Color::WHITE)?;
+    let TextureQuery { width, height, .. } = texture.query();
+    canvas.copy(&texture, None, Rect::from_center(center, width, height))?;
+    center.y += line_break;
+
+    for (i, item) in menu.items.iter().enumerate() {
+        let color = if i == menu.selected { Color::YELLOW } else { Color::WHITE };
+        let texture = text_renderer.render_text32(item, color)?;
+        let TextureQuery { width, height, .. } = texture.query();
+        center.y += text_renderer.font32.recommended_line_spacing();
+        canvas.copy(&texture, None, Rect::from_center(center, width, height))?;
+    }
+
+    Ok(())
+}
+
+fn render_editor(
+    canvas: &mut WindowCanvas,
+    camera: &Camera,
+    editor: &Editor,
+    text_renderer: &TextRenderer,
+) -> Result<(), String> {
+    if let Some(position) = editor.hover_position() {
+        let p = Point::from(camera.to_pixels(position));
+        let side = camera.scale() as u32;
+        canvas.set_draw_color(Color::YELLOW);
+        canvas.draw_rect(Rect::from_center(p, side, side))?;
+    }
+
+    let color = Color::RGB(55, 60, 66);
+    let brush = BRUSHES.iter().position(|g| *g == editor.brush()).unwrap_or_default();
+    let brush = format!("Brush {}/{}: {}", brush + 1, BRUSHES.len(), editor.brush().name());
+    let help = String::from("0-9 or wheel: brush   Ctrl+S: save   Edit: playtest");
+    let mut lines = vec![brush, help];
+    lines.extend(editor.message.clone());
+
+    let mut y = 10;
+    for line in &lines {
+        let texture = text_renderer.render_text32(line, color)?;
+        let TextureQuery { width, height, .. } = texture.query();
+        canvas.copy(&texture, None, Rect::new(10, y, width, height))?;
+        y += text_renderer.font32.recommended_line_spacing();
+    }
+    Ok(())
+}
+
+/// Draws a slope as thin columns of its sprite, each one cut at the height of the slope
+fn render_slope(
+    canvas: &mut WindowCanvas,
+    camera: &Camera,
+    texture: &Texture,
+    tile: &Tile,
+) -> Result<(), String> {
+    const STRIPS: i32 = 16;
+    let (sprite_x, sprite_y, sprite_w, sprite_h) = tile.sprite;
+    let strip_w = sprite_w as i32 / STRIPS;
+    let width = tile.sides.x / STRIPS as f32;
+    let left = tile.position.x - tile.sides.x / 2.0;
+    let bottom = tile.position.y - tile.sides.y / 2.0;
+    for i in 0..STRIPS {
+        let x = left + i as f32 * width;
+        let height = tile.top(x + width / 2.0) - bottom;
+        if height <= 0.0 {
+            continue;
+        }
+        // The top of the sprite is kept so the grass follows the slope
+        let src_h = (sprite_h as f32 * height / tile.sides.y).ceil() as u32;
+        let src = Rect::new(sprite_x + i * strip_w, sprite_y, strip_w as u32, src_h);
+        let (px, py) = camera.to_pixels(Vec2::new(x, bottom + height));
+        let size = Vec2::new(width, height) * camera.scale();
+        let dst = Rect::new(px, py, size.x.ceil() as u32, size.y.ceil() as u32);
+        canvas.copy(texture, src, dst)?;
+    }
+    Ok(())
+}
+
+/// Draws spikes as a row of triangles and lava as a pool with a rippling surface
+fn render_hazard(
+    canvas: &mut WindowCanvas,
+    camera: &Camera,
+    hazard: &Hazard,
+    time: Duration,
+) -> Result<(), String> {
+    let size = hazard.sides * camera.scale();
+    let (left, top) = camera.to_pixels(hazard.position + hazard.sides * Vec2::new(-0.5, 0.5));
+    let (width, height) = (size.x.ceil() as i32, size.y.ceil() as i32);
+    match hazard.kind {
+        HazardKind::Spikes => {
+            const SPIKES: i32 = 4;
+            canvas.set_draw_color(Color::RGB(200, 200, 210));
+            let spike = width / SPIKES;
+            for x in 0..width {
+                // Distance to the middle of the spike, 0 at its tip
+                let along = ((x % spike) as f32 / spike as f32 - 0.5).abs() * 2.0;
+                let tip = top + (along * height as f32) as i32;
+                canvas.draw_line((left + x, tip), (left + x, top + height))?;
+            }
+        }
+        HazardKind::Lava => {
+            canvas.set_draw_color(Color::RGB(220, 60, 20));
+            let t = time.as_secs_f32();
+            for x in 0..width {
+                let wave = ((left + x) as f32 / 12.0 + t * 4.0).sin() * 0.08 * height as f32;
+                canvas.draw_line((left + x, top + wave as i32), (left + x, top + height))?;
+            }
+            canvas.set_draw_color(Color::RGB(255, 170, 40));
+            let crust = Rect::new(left, top + height / 4, width as u32, (height / 6) as u32);
+            canvas.fill_rect(crust)?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn render(
     canvas: &mut WindowCanvas,
     camera: &Camera,
     level: &Level,
-    tx_manager: &TextureManager,
+    alpha: f32,
+    state: &GameState,
+    settings: &Settings,
+    campaign: &Campaign,
+    editor: Option<&Editor>,
+    tx_manager: &mut TextureManager,
     text_renderer: &TextRenderer,
 ) -> Result<(), String> {
     canvas.set_draw_color(Color::RGB(178, 220, 239));
     canvas.clear();
 
+    // Entities are drawn between their last two simulation steps, alpha being how far
+    // the time not simulated yet is into the next one
+    let player_position = level.player.previous_position.lerp(level.player.position, alpha);
+    let monkey_position = level.monkey.previous_position.lerp(level.monkey.position, alpha);
+
     // Background
     for (i, bg) in tx_manager.backgrounds.iter().enumerate() {
         let (w, h) = camera.screen_size;
         let offset = if level.trapped {
             0
         } else {
-            ((level.player.position.x + level.max_bounds().x) * (5.0 + i as f32 * 5.0)) as i32
-                % w as i32
+            ((player_position.x + level.max_bounds().x) * (5.0 + i as f32 * 5.0)) as i32 % w as i32
         };
         let dst = Rect::new(w as i32 - offset, -20, w, h);
         canvas.copy(bg, None, dst)?;
@@ -160,9 +350,9 @@ pub fn render(
         canvas.copy(bg, None, dst)?;
     }
 
-    if level.started() && !level.player.dead && level.final_time.is_none() {
+    if state.hud() {
         let color = Color::RGB(55, 60, 66);
-        let secs = level.timer.elapsed().as_secs();
+        let secs = level.time_left().unwrap_or_else(|| level.clock.now()).as_secs();
         let texture =
             text_renderer.render_text32(&format!("Time: {}:{:02}", secs / 60, secs % 60), color)?;
         let TextureQuery { width, height, .. } = texture.query();
@@ -173,15 +363,77 @@ pub fn render(
         let TextureQuery { width, height, .. } = texture.query();
         let dst = Rect::new(10, 10, width, height);
         canvas.copy(&texture, None, dst)?;
+
+        let texture =
+            text_renderer.render_text32(&format!("Lives: {}", level.player.lives), color)?;
+        let TextureQuery { width, height: lives_height, .. } = texture.query();
+        let top = 20 + height as i32;
+        canvas.copy(&texture, None, Rect::new(10, top, width, lives_height))?;
+
+        // One box per hit the player can take, the ones lost left empty
+        let size = lives_height / 2;
+        let y = top + (lives_height - size) as i32 / 2;
+        for i in 0..Player::MAX_HEALTH {
+            let x = 30 + width as i32 + (i * (size + 8)) as i32;
+            let rect = Rect::new(x, y, size, size);
+            canvas.set_draw_color(Color::RED);
+            if i < level.player.health {
+                canvas.fill_rect(rect)?;
+            } else {
+                canvas.draw_rect(rect)?;
+            }
+        }
     }
 
-    for t in &level.tiles {
+    for t in level.tiles.iter() {
+        if t.sloped() {
+            render_slope(canvas, camera, &tx_manager.tiles, t)?;
+            continue;
+        }
         let p = Point::from(camera.to_pixels(t.position));
         let rect = t.sides * camera.scale();
         let src = Rect::from(t.sprite);
         let dst = Rect::from_center(p, rect.x as u32, rect.y as u32);
         canvas.copy(&tx_manager.tiles, src, dst)?;
     }
+    for platform in &level.platforms {
+        // Row of platform tiles with the edge tiles at both ends
+        let position = platform.previous_position.lerp(platform.position, alpha);
+        let side = platform.sides.x / MovingPlatform::WIDTH as f32;
+        for i in 0..MovingPlatform::WIDTH {
+            let sprite = match i {
+                0 => Tile::PLATFORM_LEFT,
+                i if i == MovingPlatform::WIDTH - 1 => Tile::PLATFORM_RIGHT,
+                _ => Tile::PLATFORM,
+            };
+            let center = position + Vec2::X * ((i as f32 + 0.5) * side - platform.sides.x / 2.0);
+            let p = Point::from(camera.to_pixels(center));
+            let rect = Vec2::new(side, platform.sides.y) * camera.scale();
+            let dst = Rect::from_center(p, rect.x as u32, rect.y as u32);
+            canvas.copy(&tx_manager.tiles, Rect::from(sprite), dst)?;
+        }
+    }
+    for c in &level.checkpoints {
+        // Pole with a flag at the top, raised green once the checkpoint is reached
+        let bottom = c.position - Vec2::Y * c.sides.y / 2.0;
+        let (x, y) = camera.to_pixels(bottom + Vec2::new(-0.05, c.sides.y));
+        let pole = Vec2::new(0.1, c.sides.y) * camera.scale();
+        canvas.set_draw_color(Color::RGB(55, 60, 66));
+        canvas.fill_rect(Rect::new(x, y, pole.x.ceil() as u32, pole.y as u32))?;
+        let flag = Vec2::new(0.6, 0.4) * camera.scale();
+        let (flag_y, color) = if c.reached {
+            (y, Color::RGB(60, 180, 75))
+        } else {
+            (y + (pole.y - flag.y * 1.5) as i32, Color::RGB(220, 60, 20))
+        };
+        canvas.set_draw_color(color);
+        canvas.fill_rect(Rect::new(
+            x + pole.x.ceil() as i32,
+            flag_y,
+            flag.x as u32,
+            flag.y as u32,
+        ))?;
+    }
     for (i, t) in level.decor.iter().enumerate() {
         let p = Point::from(camera.to_pixels(t.position));
         let rect = t.sides * camera.scale();
@@ -192,7 +444,7 @@ pub fn render(
     }
 
     for (i, e) in level.enemies.iter().enumerate() {
-        let p = Point::from(camera.to_pixels(e.position));
+        let p = Point::from(camera.to_pixels(e.previous_position.lerp(e.position, alpha)));
         canvas.set_draw_color(Color::BLACK);
         let rect = e.sides * camera.scale();
         if !e.dead() {
@@ -217,7 +469,7 @@ pub fn render(
         }
     }
 
-    let p = Point::from(camera.to_pixels(level.monkey.position));
+    let p = Point::from(camera.to_pixels(monkey_position));
     let color = if level.monkey.enranged { Color::RED } else { Color::YELLOW };
     canvas.set_draw_color(color);
     let rect = level.monkey.sides * camera.scale();
@@ -228,10 +480,9 @@ pub fn render(
 
         if level.monkey.enranged {
             let texture = text_renderer.render_text32("RAGE!", Color::RED)?;
-            let overhead =
-                Point::from(camera.to_pixels(
-                    level.monkey.position + Vec2::Y * (level.monkey.sides.y / 2.0 + 0.5),
-                ));
+            let overhead = Point::from(
+                camera.to_pixels(monkey_position + Vec2::Y * (level.monkey.sides.y / 2.0 + 0.5)),
+            );
             let TextureQuery { width, height, .. } = texture.query();
             let dst = Rect::from_center(overhead, width, height);
             canvas.copy(&texture, None, dst)?;
@@ -251,7 +502,7 @@ pub fn render(
     // canvas.fill_rect(Rect::from_center(head_point, rect.x as u32, rect.y as u32))?;
 
     for b in &level.monkey.bananas {
-        let p = Point::from(camera.to_pixels(b.position));
+        let p = Point::from(camera.to_pixels(b.previous_position.lerp(b.position, alpha)));
         canvas.set_draw_color(Color::YELLOW);
         let rect = b.sides * camera.scale();
         let dst = Rect::from_center(p, rect.x as u32, rect.y as u32);
@@ -259,11 +510,31 @@ pub fn render(
     }
 
     // Render Player
-    let p = Point::from(camera.to_pixels(level.player.position));
+    let p = Point::from(camera.to_pixels(player_position));
     let src = Rect::from(level.player.sprite);
     let rect = level.player.sides() * camera.scale();
     let dst = Rect::from_center(p, rect.x as u32, rect.y as u32);
-    canvas.copy_ex(&tx_manager.jeff, src, dst, 0.0, None, level.player.velocity.x < 0.0, false)?;
+    let upside_down = level.player.death_cause == Some(DeathCause::Spikes);
+    let left = level.player.facing_left();
+    // Fading copies of the player where it was a moment ago, the oldest ones fainter
+    let trail = &level.player.trail;
+    for (i, position) in trail.iter().enumerate() {
+        let alpha = 160 * (i + 1) / (trail.len() + 1);
+        tx_manager.jeff.set_alpha_mod(alpha as u8);
+        let dst = Rect::from_center(camera.to_pixels(*position), rect.x as u32, rect.y as u32);
+        canvas.copy_ex(&tx_manager.jeff, src, dst, 0.0, None, left, false)?;
+    }
+    tx_manager.jeff.set_alpha_mod(255);
+    // Flashes while invulnerable, hidden every other tenth of a second
+    let hidden = (level.player.invulnerable() * 10.0) as i32 % 2 == 1;
+    if !hidden {
+        canvas.copy_ex(&tx_manager.jeff, src, dst, 0.0, None, left, upside_down)?;
+    }
+
+    // In front of the player, so it sinks into the lava
+    for h in &level.hazards {
+        render_hazard(canvas, camera, h, level.clock.now())?;
+    }
 
     // Hitbox
     // let p = Point::from(camera.to_pixels(level.player.hitbox_position()));
@@ -282,38 +553,57 @@ pub fn render(
     // canvas.fill_rect(Rect::from_center(camera_point, 4, 4))?;
 
     // Overlays
-    if level.player.dead {
+    if let (GameState::Editing, Some(editor)) = (state, editor) {
+        render_editor(canvas, camera, editor, text_renderer)?;
+    } else if let Some(menu) = state.menu(settings, campaign) {
+        render_menu(canvas, camera, &menu, text_renderer)?;
+    } else if *state == GameState::GameOver {
         canvas.copy(&tx_manager.gameover, None, None)?;
-    } else if !level.started() {
+    } else if *state == GameState::Title {
         canvas.copy(&tx_manager.newgame, None, None)?;
-    } else if let Some(time) = level.final_time {
-        canvas.copy(&tx_manager.endgame, None, None)?;
+    } else if let (GameState::Victory, Some(time)) = (state, level.final_time) {
+        let format_time =
+            |t: Duration| format!("Time: {}:{:02}", t.as_secs() / 60, t.as_secs() % 60);
+        let mut lines = if campaign.last() {
+            canvas.copy(&tx_manager.endgame, None, None)?;
+            vec![
+                format!("Score: {}", level.score),
+                format_time(campaign.total_time(level)),
+                format!("Total: {}", campaign.final_score(level)),
+            ]
+        } else {
+            canvas.set_blend_mode(BlendMode::Blend);
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+            canvas.fill_rect(None)?;
+            canvas.set_blend_mode(BlendMode::None);
+            vec![
+                String::from(level.header.name.as_deref().unwrap_or("Level Complete")),
+                format!("Score: {}", level.score),
+                format_time(time),
+            ]
+        };
+        if let Some(par) = level.header.par_time {
+            lines.push(format!("Par: {}:{:02}", par.as_secs() / 60, par.as_secs() % 60));
+        }
+
         let line_break = text_renderer.font64.recommended_line_spacing();
         let (w, h) = camera.screen_size;
         let mut center = Point::new(w as i32 / 2, h as i32 / 4);
+        for line in &lines {
+            let texture = text_renderer.render_text64(line, Color::WHITE)?;
+            let TextureQuery { width, height, .. } = texture.query();
+            center.y += line_break;
+            let dst = Rect::from_center(center, width, height);
+            canvas.copy(&texture, None, dst)?;
+        }
 
-        let texture =
-            text_renderer.render_text64(&format!("Score: {}", level.score), Color::WHITE)?;
-        let TextureQuery { width, height, .. } = texture.query();
-        center.y += line_break;
-        let dst = Rect::from_center(center, width, height);
-        canvas.copy(&texture, None, dst)?;
-
-        let texture = text_renderer.render_text64(
-            &format!("Time: {}:{:02}", time.as_secs() / 60, time.as_secs() % 60),
-            Color::WHITE,
-        )?;
-        let TextureQuery { width, height, .. } = texture.query();
-        center.y += line_break;
-        let dst = Rect::from_center(center, width, height);
-        canvas.copy(&texture, None, dst)?;
-
-        let texture = text_renderer
-            .render_text64(&format!("Total: {}", level.final_score()), Color::WHITE)?;
-        let TextureQuery { width, height, .. } = texture.query();
-        center.y += line_break;
-        let dst = Rect::from_center(center, width, height);
-        canvas.copy(&texture, None, dst)?;
+        if !campaign.last() {
+            let texture = text_renderer.render_text32("Press Jump to continue", Color::WHITE)?;
+            let TextureQuery { width, height, .. } = texture.query();
+            center.y += line_break;
+            let dst = Rect::from_center(center, width, height);
+            canvas.copy(&texture, None, dst)?;
+        }
     }
 
     canvas.present();
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use crate::input::Action;
+
+/// Recorded run of a level, with the random seed and the elapsed time and
+/// active actions of every frame so it can be played back exactly.
+///
+/// Replays are stored as text, a `seed <n>` and a `level <file>` header
+/// followed by one `<elapsed> [action ...]` line per frame.
+#[derive(Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub level: String,
+    pub frames: Vec<(f32, HashSet<Action>)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, level: &str) -> Replay {
+        Replay { seed, level: level.to_string(), frames: Vec::new() }
+    }
+
+    pub fn record(&mut self, elapsed: f32, actions: &HashSet<Action>) {
+        self.frames.push((elapsed, actions.clone()));
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(filename)?);
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "level {}", self.level)?;
+        for (elapsed, actions) in &self.frames {
+            write!(writer, "{}", elapsed)?;
+            // Keep a stable order so identical runs produce identical files
+            for action in Action::ALL.iter().filter(|a| actions.contains(a)) {
+                write!(writer, " {}", action.name())?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    pub fn from_file(filename: &str) -> Result<Replay, String> {
+        let replay_str = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        let mut lines = replay_str.lines().enumerate();
+
+        let mut header = |key: &str| {
+            lines
+                .next()
+                .and_then(|(_, line)| line.strip_prefix(key))
+                .map(|value| value.trim().to_string())
+                .ok_or_else(|| format!("{}: missing '{}' header", filename, key))
+        };
+        let seed =
+            header("seed")?.parse().map_err(|e| format!("{}: invalid seed: {}", filename, e))?;
+        let level = header("level")?;
+
+        let mut replay = Replay::new(seed, &level);
+        for (i, line) in lines {
+            let mut tokens = line.split_whitespace();
+            let elapsed = match tokens.next() {
+                Some(t) => t
+                    .parse()
+                    .ok()
+                    .filter(|elapsed: &f32| elapsed.is_finite() && *elapsed >= 0.0)
+                    .ok_or_else(|| format!("{}:{}: invalid elapsed time", filename, i + 1))?,
+                None => continue,
+            };
+            let actions = tokens
+                .map(|t| {
+                    Action::from_name(t)
+                        .ok_or_else(|| format!("{}:{}: unknown action '{}'", filename, i + 1, t))
+                })
+                .collect::<Result<_, _>>()?;
+            replay.frames.push((elapsed, actions));
+        }
+
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // Writes the contents to a file of its own in the temporary directory
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("super-jeff-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn record_then_parse() {
+        let mut replay = Replay::new(42, "assets/level.txt");
+        replay.record(1.0 / 60.0, &HashSet::new());
+        replay.record(0.02, &[Action::MoveRight, Action::Jump].iter().copied().collect());
+        let filename = temp_file("round-trip.replay", "");
+        replay.save(&filename).unwrap();
+
+        let parsed = Replay::from_file(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(parsed.seed, 42);
+        assert_eq!(parsed.level, "assets/level.txt");
+        assert_eq!(parsed.frames, replay.frames);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let filename = temp_file("blank.replay", "seed 1\nlevel a.txt\n\n0.5 Crouch\n");
+        let replay = Replay::from_file(&filename).unwrap();
+        fs::remove_file(&filename).unwrap();
+        assert_eq!(replay.frames, vec![(0.5, [Action::Crouch].iter().copied().collect())]);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let cases = [
+            ("no-seed.replay", "level a.txt\n", "missing 'seed' header"),
+            ("bad-seed.replay", "seed x\nlevel a.txt\n", "invalid seed"),
+            ("no-level.replay", "seed 1\n", "missing 'level' header"),
+            ("bad-time.replay", "seed 1\nlevel a.txt\nsoon Jump\n", ":3: invalid elapsed time"),
+            ("negative-time.replay", "seed 1\nlevel a.txt\n-0.1\n", ":3: invalid elapsed time"),
+            ("infinite-time.replay", "seed 1\nlevel a.txt\n0.1\ninf\n", ":4: invalid elapsed time"),
+            ("nan-time.replay", "seed 1\nlevel a.txt\nNaN Jump\n", ":3: invalid elapsed time"),
+            ("bad-action.replay", "seed 1\nlevel a.txt\n0.1 Fly\n", ":3: unknown action 'Fly'"),
+        ];
+        for (name, contents, message) in cases.iter() {
+            let filename = temp_file(name, contents);
+            let error = Replay::from_file(&filename).unwrap_err();
+            fs::remove_file(&filename).unwrap();
+            assert!(error.contains(message), "{}: {}", name, error);
+        }
+        assert!(Replay::from_file("missing.replay").is_err());
+    }
+}
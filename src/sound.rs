@@ -1,7 +1,8 @@
 use sdl2::mixer::{self, Chunk};
 use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
 pub enum SoundEffect {
     Jump,
     Hit,
@@ -10,14 +11,38 @@ pub enum SoundEffect {
     Fall,
     Banana,
     Rage,
+    Spikes,
+    Lava,
+    DoubleJump,
+    WallSlide,
+    WallJump,
+    Dash,
 }
 
 pub struct Sound<'a> {
     sound_registry: HashMap<SoundEffect, Chunk>,
     music: mixer::Music<'a>,
+    music_file: String,
 }
 
 impl<'a> Sound<'a> {
+    const MUSIC: &'static str = "assets/music.ogg";
+    const EFFECTS: [(SoundEffect, &'static str); 13] = [
+        (SoundEffect::Jump, "assets/jump.wav"),
+        (SoundEffect::Hit, "assets/hit.wav"),
+        (SoundEffect::Click, "assets/click.wav"),
+        (SoundEffect::Dead, "assets/dead.wav"),
+        (SoundEffect::Fall, "assets/fall.wav"),
+        (SoundEffect::Banana, "assets/banana.wav"),
+        (SoundEffect::Rage, "assets/rage.wav"),
+        (SoundEffect::Spikes, "assets/spikes.wav"),
+        (SoundEffect::Lava, "assets/lava.wav"),
+        (SoundEffect::DoubleJump, "assets/doublejump.wav"),
+        (SoundEffect::WallSlide, "assets/slide.wav"),
+        (SoundEffect::WallJump, "assets/walljump.wav"),
+        (SoundEffect::Dash, "assets/dash.wav"),
+    ];
+
     pub fn load() -> Result<Sound<'a>, String> {
         let frequency = 44_100;
         let format = mixer::AUDIO_S16LSB; // signed 16 bit samples, in little-endian byte order
@@ -26,27 +51,53 @@ impl<'a> Sound<'a> {
         sdl2::mixer::open_audio(frequency, format, channels, chunk_size)?;
         mixer::allocate_channels(8);
 
-        let mut sound_registry: HashMap<SoundEffect, Chunk> = HashMap::new();
-
-        use SoundEffect::*;
-        sound_registry.insert(Jump, Chunk::from_file("assets/jump.wav")?);
-        sound_registry.insert(Hit, Chunk::from_file("assets/hit.wav")?);
-        sound_registry.insert(Click, Chunk::from_file("assets/click.wav")?);
-        sound_registry.insert(Dead, Chunk::from_file("assets/dead.wav")?);
-        sound_registry.insert(Fall, Chunk::from_file("assets/fall.wav")?);
-        sound_registry.insert(Banana, Chunk::from_file("assets/banana.wav")?);
-        sound_registry.insert(Rage, Chunk::from_file("assets/rage.wav")?);
+        let sound_registry = Sound::EFFECTS
+            .iter()
+            .map(|(effect, file)| Ok((*effect, Chunk::from_file(file)?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
 
-        let music = mixer::Music::from_file("assets/music.ogg")?;
+        let music = mixer::Music::from_file(Sound::MUSIC)?;
         mixer::Music::set_volume(24);
 
-        Ok(Sound { sound_registry, music })
+        Ok(Sound { sound_registry, music, music_file: Sound::MUSIC.to_string() })
     }
 
     pub fn play_music(&self) -> Result<(), String> {
         self.music.play(-1)
     }
 
+    /// Switches to another music track, going back to the default one when none is given
+    pub fn play_track(&mut self, filename: Option<&str>) -> Result<(), String> {
+        let filename = filename.unwrap_or(Sound::MUSIC);
+        if filename != self.music_file {
+            self.music = mixer::Music::from_file(filename)?;
+            self.music_file = filename.to_string();
+            self.play_music()?;
+        }
+        Ok(())
+    }
+
+    /// Loads a sound effect or the current music track again after it changed on disk
+    pub fn reload(&mut self, filename: &Path) -> Result<(), String> {
+        if let Some((effect, file)) = Sound::EFFECTS.iter().find(|(_, f)| Path::new(f) == filename)
+        {
+            self.sound_registry.insert(*effect, Chunk::from_file(file)?);
+        }
+        if Path::new(&self.music_file) == filename {
+            self.music = mixer::Music::from_file(filename)?;
+            self.play_music()?;
+        }
+        Ok(())
+    }
+
+    pub fn set_music(&self, on: bool) {
+        if on {
+            mixer::Music::resume();
+        } else {
+            mixer::Music::pause();
+        }
+    }
+
     pub fn play_sounds(&self, sounds: Vec<SoundEffect>) {
         for s in &sounds {
             if let Some(s) = self.sound_registry.get(s) {
@@ -0,0 +1,175 @@
+use crate::campaign::Campaign;
+use crate::input::Action;
+use crate::level::Level;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameState {
+    Title,
+    LevelSelect(usize),
+    Playing,
+    Paused(usize),
+    Options(usize),
+    GameOver,
+    Victory,
+    Editing,
+}
+
+/// Side effects of a state transition, carried out by the main loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Start,
+    Restart,
+    NextLevel,
+    SelectLevel(usize),
+    Quit,
+    ApplySettings,
+    Edit,
+    Playtest,
+}
+
+#[derive(Debug)]
+pub struct Settings {
+    pub music: bool,
+    pub sounds: bool,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings { music: true, sounds: true }
+    }
+}
+
+#[derive(Debug)]
+pub struct Menu {
+    pub title: &'static str,
+    pub items: Vec<String>,
+    pub selected: usize,
+}
+
+const PAUSE_ITEMS: [&str; 5] = ["Resume", "Restart", "Levels", "Options", "Quit"];
+const PAUSE_LEVELS: usize = 2;
+const PAUSE_OPTIONS: usize = 3;
+const OPTIONS_ITEMS: usize = 3;
+
+// Moves the menu selection up or down, wrapping around
+fn navigate(selected: usize, len: usize, action: Action) -> usize {
+    match action {
+        Action::MoveUp => (selected + len - 1) % len,
+        Action::Crouch => (selected + 1) % len,
+        _ => selected,
+    }
+}
+
+impl GameState {
+    /// Whether the level simulation runs in this state
+    pub fn simulating(&self) -> bool {
+        matches!(self, GameState::Playing | GameState::Victory)
+    }
+
+    /// Whether the time and score are displayed in this state
+    pub fn hud(&self) -> bool {
+        matches!(self, GameState::Playing | GameState::Paused(_) | GameState::Options(_))
+    }
+
+    pub fn pressed(
+        self,
+        action: Action,
+        campaign: &Campaign,
+        level: &Level,
+    ) -> (GameState, Option<Command>) {
+        use GameState::*;
+        match (self, action) {
+            (Title, Action::Pause) => (Title, Some(Command::Quit)),
+            (Title, Action::MoveUp) | (Title, Action::Crouch) => {
+                (LevelSelect(campaign.current()), None)
+            }
+            (Title, _) => (Title, None),
+            // Back to the pause menu it was opened from, the level in progress going on
+            (LevelSelect(_), Action::Pause) if level.started() => (Paused(PAUSE_LEVELS), None),
+            (LevelSelect(_), Action::Pause) => (Title, None),
+            (LevelSelect(i), _) => (LevelSelect(navigate(i, campaign.unlocked(), action)), None),
+            (Paused(_), Action::Pause) => (Playing, None),
+            (Paused(i), _) => (Paused(navigate(i, PAUSE_ITEMS.len(), action)), None),
+            (Options(_), Action::Pause) => (Paused(PAUSE_OPTIONS), None),
+            (Options(i), _) => (Options(navigate(i, OPTIONS_ITEMS, action)), None),
+            (Editing, Action::Edit) => (Playing, Some(Command::Playtest)),
+            // Leaves the editor without playtesting, the edits kept for the next time
+            (Editing, Action::Pause) if level.started() => (Paused(0), None),
+            (Editing, Action::Pause) => (Title, None),
+            (Editing, _) => (Editing, None),
+            (_, Action::Edit) => (Editing, Some(Command::Edit)),
+            (_, Action::Restart) => (Playing, Some(Command::Restart)),
+            (Playing, Action::Pause) => (Paused(0), None),
+            // Back to the title screen with the level reloaded, ready to be started again
+            (GameOver, Action::Pause) | (Victory, Action::Pause) => (Title, Some(Command::Restart)),
+            _ => (self, None),
+        }
+    }
+
+    // Confirmation happens on release so the jump isn't carried into the game
+    pub fn released(
+        self,
+        action: Action,
+        settings: &mut Settings,
+        campaign: &Campaign,
+    ) -> (GameState, Option<Command>) {
+        use GameState::*;
+        match (self, action) {
+            (Title, Action::Jump) => (Playing, Some(Command::Start)),
+            (LevelSelect(i), Action::Jump) => (Playing, Some(Command::SelectLevel(i))),
+            (Victory, Action::Jump) if !campaign.last() => (Playing, Some(Command::NextLevel)),
+            (Paused(i), Action::Jump) => match i {
+                0 => (Playing, None),
+                1 => (Playing, Some(Command::Restart)),
+                PAUSE_LEVELS => (LevelSelect(campaign.current()), None),
+                PAUSE_OPTIONS => (Options(0), None),
+                _ => (self, Some(Command::Quit)),
+            },
+            (Options(i), Action::Jump) => match i {
+                0 => {
+                    settings.music = !settings.music;
+                    (self, Some(Command::ApplySettings))
+                }
+                1 => {
+                    settings.sounds = !settings.sounds;
+                    (self, Some(Command::ApplySettings))
+                }
+                _ => (Paused(PAUSE_OPTIONS), None),
+            },
+            _ => (self, None),
+        }
+    }
+
+    /// Transitions caused by the outcome of the level
+    pub fn update(self, level: &Level) -> GameState {
+        match self {
+            GameState::Playing if level.player.game_over() => GameState::GameOver,
+            GameState::Playing if level.final_time.is_some() => GameState::Victory,
+            _ => self,
+        }
+    }
+
+    pub fn menu(&self, settings: &Settings, campaign: &Campaign) -> Option<Menu> {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match *self {
+            GameState::LevelSelect(selected) => {
+                Some(Menu { title: "Select Level", items: campaign.level_names(), selected })
+            }
+            GameState::Paused(selected) => Some(Menu {
+                title: "Paused",
+                items: PAUSE_ITEMS.iter().map(|s| s.to_string()).collect(),
+                selected,
+            }),
+            GameState::Options(selected) => Some(Menu {
+                title: "Options",
+                items: vec![
+                    format!("Music: {}", on_off(settings.music)),
+                    format!("Sounds: {}", on_off(settings.sounds)),
+                    String::from("Back"),
+                ],
+                selected,
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::fs;
+use std::path::Path;
+
+use crate::header::{Glyph, LevelHeader};
+use crate::level::LevelError;
+
+/// Glyphs of the tiles in `tiles.png`, in the order they appear in the tileset
+const TILESET: [Glyph; 14] = [
+    Glyph::TileLeft,
+    Glyph::TileRight,
+    Glyph::TileCenter,
+    Glyph::TileBottom,
+    Glyph::Platform,
+    Glyph::SlopeRight,
+    Glyph::SlopeLeft,
+    Glyph::RampRightLow,
+    Glyph::RampRightHigh,
+    Glyph::RampLeftHigh,
+    Glyph::RampLeftLow,
+    Glyph::Spikes,
+    Glyph::Lava,
+    Glyph::Checkpoint,
+];
+
+/// Name of the tile layer holding decor instead of solid tiles
+const DECOR_LAYER: &str = "decor";
+
+// Tiled stores flipping and rotation in the highest bits of the tile ids
+const GID_MASK: u32 = 0x1fff_ffff;
+
+/// Level read from a Tiled map, as the list of glyphs placed on its grid
+#[derive(Debug)]
+pub struct TiledMap {
+    pub header: LevelHeader,
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<(usize, usize, Glyph)>,
+}
+
+#[derive(Debug)]
+struct Object {
+    kind: String,
+    x: f32,
+    y: f32,
+    tile: bool,
+}
+
+#[derive(Debug)]
+enum Layer {
+    Tiles { name: String, data: Vec<u32> },
+    Objects(Vec<Object>),
+}
+
+/// Contents of a map common to the XML and JSON formats
+#[derive(Debug, Default)]
+struct Map {
+    width: usize,
+    height: usize,
+    tile_width: f32,
+    tile_height: f32,
+    properties: Vec<(String, String)>,
+    first_gids: Vec<u32>,
+    layers: Vec<Layer>,
+}
+
+fn error<E: ToString>(e: E) -> LevelError {
+    LevelError::Tiled(e.to_string())
+}
+
+/// Whether the file is a Tiled map, judging by its extension
+pub fn is_tiled(filename: &str) -> bool {
+    matches!(Path::new(filename).extension().and_then(|e| e.to_str()), Some("tmx" | "tmj" | "json"))
+}
+
+pub fn from_file(filename: &str) -> Result<TiledMap, LevelError> {
+    let contents = fs::read_to_string(filename)?;
+    let map =
+        if filename.ends_with(".tmx") { parse_tmx(&contents)? } else { parse_json(&contents)? };
+    map.into_level()
+}
+
+fn parse_csv(data: &str) -> Result<Vec<u32>, LevelError> {
+    data.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|_| error(format!("invalid tile id '{}'", s))))
+        .collect()
+}
+
+fn parse_tmx(contents: &str) -> Result<Map, LevelError> {
+    let doc = roxmltree::Document::parse(contents).map_err(error)?;
+    let root = doc.root_element();
+    let attr = |node: roxmltree::Node, name: &str| -> Result<f32, LevelError> {
+        node.attribute(name)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| error(format!("missing or invalid attribute '{}'", name)))
+    };
+
+    let mut map = Map {
+        width: attr(root, "width")? as usize,
+        height: attr(root, "height")? as usize,
+        tile_width: attr(root, "tilewidth")?,
+        tile_height: attr(root, "tileheight")?,
+        ..Map::default()
+    };
+    for node in root.children().filter(|n| n.is_element()) {
+        match node.tag_name().name() {
+            "properties" => {
+                for p in node.children().filter(|n| n.has_tag_name("property")) {
+                    let name = p.attribute("name").unwrap_or_default();
+                    let value = p.attribute("value").or_else(|| p.text()).unwrap_or_default();
+                    map.properties.push((name.to_string(), value.to_string()));
+                }
+            }
+            "tileset" => map.first_gids.push(attr(node, "firstgid")? as u32),
+            "layer" => {
+                let data = node
+                    .children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or_else(|| error("tile layer without data"))?;
+                match data.attribute("encoding") {
+                    Some("csv") => {}
+                    Some(encoding) => {
+                        return Err(error(format!("unsupported layer encoding '{}'", encoding)))
+                    }
+                    None => return Err(error("unsupported layer encoding 'xml'")),
+                }
+                map.layers.push(Layer::Tiles {
+                    name: node.attribute("name").unwrap_or_default().to_string(),
+                    data: parse_csv(data.text().unwrap_or_default())?,
+                });
+            }
+            "objectgroup" => {
+                let objects = node
+                    .children()
+                    .filter(|n| n.has_tag_name("object"))
+                    .map(|o| {
+                        let kind = ["class", "type", "name"]
+                            .iter()
+                            .filter_map(|name| o.attribute(*name))
+                            .find(|s| !s.is_empty());
+                        Ok(Object {
+                            kind: kind.unwrap_or_default().to_string(),
+                            x: attr(o, "x")?,
+                            y: attr(o, "y")?,
+                            tile: o.attribute("gid").is_some(),
+                        })
+                    })
+                    .collect::<Result<_, LevelError>>()?;
+                map.layers.push(Layer::Objects(objects));
+            }
+            _ => {}
+        }
+    }
+    Ok(map)
+}
+
+fn parse_json(contents: &str) -> Result<Map, LevelError> {
+    let root = json::parse(contents).map_err(error)?;
+    let number = |value: &json::JsonValue, name: &str| -> Result<f32, LevelError> {
+        value[name].as_f32().ok_or_else(|| error(format!("missing or invalid field '{}'", name)))
+    };
+    let string = |value: &json::JsonValue| match value.as_str() {
+        Some(s) => s.to_string(),
+        None => value.dump(),
+    };
+
+    let mut map = Map {
+        width: number(&root, "width")? as usize,
+        height: number(&root, "height")? as usize,
+        tile_width: number(&root, "tilewidth")?,
+        tile_height: number(&root, "tileheight")?,
+        ..Map::default()
+    };
+    for p in root["properties"].members() {
+        map.properties.push((string(&p["name"]), string(&p["value"])));
+    }
+    for tileset in root["tilesets"].members() {
+        map.first_gids.push(number(tileset, "firstgid")? as u32);
+    }
+    for layer in root["layers"].members() {
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                if !layer["data"].is_array() {
+                    return Err(error("unsupported layer encoding, save the map with CSV"));
+                }
+                let data = layer["data"]
+                    .members()
+                    .map(|gid| gid.as_u32().ok_or_else(|| error("invalid tile id")))
+                    .collect::<Result<_, _>>()?;
+                map.layers.push(Layer::Tiles { name: string(&layer["name"]), data });
+            }
+            Some("objectgroup") => {
+                let objects = layer["objects"]
+                    .members()
+                    .map(|o| {
+                        let kind = [&o["class"], &o["type"], &o["name"]]
+                            .iter()
+                            .filter_map(|v| v.as_str())
+                            .find(|s| !s.is_empty());
+                        Ok(Object {
+                            kind: kind.unwrap_or_default().to_string(),
+                            x: number(o, "x")?,
+                            y: number(o, "y")?,
+                            tile: !o["gid"].is_null(),
+                        })
+                    })
+                    .collect::<Result<_, LevelError>>()?;
+                map.layers.push(Layer::Objects(objects));
+            }
+            _ => {}
+        }
+    }
+    Ok(map)
+}
+
+impl Map {
+    // Glyph of a tile id, relative to the tileset it belongs to
+    fn glyph(&self, gid: u32) -> Result<Glyph, LevelError> {
+        let first_gid = self.first_gids.iter().copied().filter(|&first| first <= gid).max();
+        first_gid
+            .and_then(|first| TILESET.get((gid - first) as usize).copied())
+            .ok_or_else(|| error(format!("tile id {} is not in the tileset", gid)))
+    }
+
+    fn into_level(self) -> Result<TiledMap, LevelError> {
+        // Map properties are read like the header of a text level
+        let lines: Vec<String> =
+            self.properties.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let header = LevelHeader::parse(&lines).map_err(|e| match e {
+            LevelError::Header { line, message } => {
+                error(format!("property '{}': {}", self.properties[line - 1].0, message))
+            }
+            e => e,
+        })?;
+
+        if self.width == 0 || self.height == 0 {
+            return Err(error("map has no tiles"));
+        }
+
+        let mut cells = Vec::new();
+        for layer in &self.layers {
+            match layer {
+                Layer::Tiles { name, data } => {
+                    if data.len() != self.width * self.height {
+                        return Err(error(format!("layer '{}' does not match the map size", name)));
+                    }
+                    let decor = name.eq_ignore_ascii_case(DECOR_LAYER);
+                    for (i, gid) in data.iter().map(|gid| gid & GID_MASK).enumerate() {
+                        if gid == 0 {
+                            continue;
+                        }
+                        let glyph = if decor { Glyph::Decor } else { self.glyph(gid)? };
+                        cells.push((i % self.width, i / self.width, glyph));
+                    }
+                }
+                Layer::Objects(objects) => {
+                    for o in objects {
+                        let glyph = Glyph::from_name(&o.kind.to_lowercase())
+                            .filter(|&g| g != Glyph::Empty)
+                            .ok_or_else(|| error(format!("unknown object type '{}'", o.kind)))?;
+                        // Tile objects are anchored at their bottom left corner
+                        let y = if o.tile { o.y - self.tile_height / 2.0 } else { o.y };
+                        let x = (o.x / self.tile_width).floor().max(0.0) as usize;
+                        let y = (y / self.tile_height).floor().max(0.0) as usize;
+                        cells.push((x.min(self.width - 1), y.min(self.height - 1), glyph));
+                    }
+                }
+            }
+        }
+
+        Ok(TiledMap { header, width: self.width, height: self.height, cells })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Same 3x2 map in both formats: a tile row with a flipped tile, a decor tile and
+    // spawn, boss and trap objects
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map width="3" height="2" tilewidth="64" tileheight="64">
+ <properties>
+  <property name="name" value="Tiled"/>
+  <property name="time_limit" value="1:00"/>
+ </properties>
+ <tileset firstgid="1" source="tiles.tsx"/>
+ <layer name="ground" width="3" height="2">
+  <data encoding="csv">
+0,0,0,
+1,3,2147483650
+</data>
+ </layer>
+ <layer name="Decor" width="3" height="2">
+  <data encoding="csv">4,0,0,0,0,0</data>
+ </layer>
+ <objectgroup name="entities">
+  <object id="1" type="spawn" x="10" y="20"/>
+  <object id="2" name="boss" x="130" y="63"/>
+  <object id="3" class="trap" gid="1" x="64" y="64"/>
+ </objectgroup>
+</map>
+"#;
+
+    const JSON: &str = r#"{
+ "width": 3, "height": 2, "tilewidth": 64, "tileheight": 64,
+ "properties": [
+  {"name": "name", "type": "string", "value": "Tiled"},
+  {"name": "time_limit", "type": "string", "value": "1:00"}
+ ],
+ "tilesets": [{"firstgid": 1, "source": "tiles.tsx"}],
+ "layers": [
+  {"type": "tilelayer", "name": "ground", "data": [0, 0, 0, 1, 3, 2147483650]},
+  {"type": "tilelayer", "name": "Decor", "data": [4, 0, 0, 0, 0, 0]},
+  {"type": "objectgroup", "name": "entities", "objects": [
+   {"type": "spawn", "x": 10, "y": 20},
+   {"name": "boss", "x": 130, "y": 63},
+   {"class": "trap", "gid": 1, "x": 64, "y": 64}
+  ]}
+ ]
+}"#;
+
+    fn expected_cells() -> Vec<(usize, usize, Glyph)> {
+        vec![
+            (0, 1, Glyph::TileLeft),
+            (1, 1, Glyph::TileCenter),
+            (2, 1, Glyph::TileRight),
+            (0, 0, Glyph::Decor),
+            (0, 0, Glyph::Spawn),
+            (2, 0, Glyph::Boss),
+            (1, 0, Glyph::Trap),
+        ]
+    }
+
+    fn error_message(result: Result<Map, LevelError>) -> String {
+        match result.and_then(Map::into_level) {
+            Err(LevelError::Tiled(message)) => message,
+            other => panic!("expected a Tiled error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_tmx() {
+        let map = parse_tmx(TMX).unwrap().into_level().unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.header.name.as_deref(), Some("Tiled"));
+        assert_eq!(map.header.time_limit, Some(std::time::Duration::from_secs(60)));
+        assert_eq!(map.cells, expected_cells());
+    }
+
+    #[test]
+    fn json_matches_tmx() {
+        let map = parse_json(JSON).unwrap().into_level().unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.header.name.as_deref(), Some("Tiled"));
+        assert_eq!(map.cells, expected_cells());
+    }
+
+    #[test]
+    fn every_tile_of_the_tileset_has_a_glyph() {
+        let map = Map { first_gids: vec![1, 100], ..Map::default() };
+        let glyphs: Vec<Glyph> =
+            (1..=TILESET.len() as u32).map(|gid| map.glyph(gid).unwrap()).collect();
+        assert_eq!(glyphs, TILESET);
+        assert_eq!(map.glyph(104).unwrap(), Glyph::Platform);
+        assert_eq!(map.glyph(14).unwrap(), Glyph::Checkpoint);
+        assert!(map.glyph(15).is_err());
+    }
+
+    #[test]
+    fn recognizes_extensions() {
+        assert!(is_tiled("maps/one.tmx") && is_tiled("one.tmj") && is_tiled("one.json"));
+        assert!(!is_tiled("assets/level.txt"));
+    }
+
+    #[test]
+    fn malformed_tmx() {
+        assert!(parse_tmx("<map").is_err());
+        assert_eq!(
+            error_message(parse_tmx(r#"<map width="3" tilewidth="64" tileheight="64"/>"#)),
+            "missing or invalid attribute 'height'"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace("encoding=\"csv\"", "encoding=\"base64\""))),
+            "unsupported layer encoding 'base64'"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace(",\n1,3", ",\nx,3"))),
+            "invalid tile id 'x'"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace("4,0,0,0,0,0", "4,0,0"))),
+            "layer 'Decor' does not match the map size"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace("1,3,2147483650", "1,3,99"))),
+            "tile id 99 is not in the tileset"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace("type=\"spawn\"", "type=\"dragon\""))),
+            "unknown object type 'dragon'"
+        );
+        assert_eq!(
+            error_message(parse_tmx(&TMX.replace("value=\"1:00\"", "value=\"later\""))),
+            "property 'time_limit': invalid time"
+        );
+    }
+
+    #[test]
+    fn malformed_json() {
+        assert!(parse_json("{").is_err());
+        assert_eq!(
+            error_message(parse_json(&JSON.replace("\"width\": 3, ", ""))),
+            "missing or invalid field 'width'"
+        );
+        assert_eq!(
+            error_message(parse_json(&JSON.replace("[0, 0, 0, 1, 3, 2147483650]", "\"eJwz\""))),
+            "unsupported layer encoding, save the map with CSV"
+        );
+        assert_eq!(
+            error_message(parse_json(
+                &JSON.replace("\"width\": 3, \"height\": 2", "\"width\": 0, \"height\": 0")
+            )),
+            "map has no tiles"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Finds the files of a directory changed on disk by polling their modification time
+#[derive(Debug)]
+pub struct Watcher {
+    dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(dir: &str) -> Watcher {
+        let mut watcher = Watcher {
+            dir: PathBuf::from(dir),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.scan();
+        watcher
+    }
+
+    // Files created or modified since the last scan
+    fn scan(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return changed,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let metadata = match entry.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            if let Ok(modified) = metadata.modified() {
+                let path = entry.path();
+                if self.modified.insert(path.clone(), modified) != Some(modified) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Files changed since the last poll, looking at the disk every INTERVAL at most
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < Watcher::INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        self.scan()
+    }
+}